@@ -0,0 +1,145 @@
+use crate::camera::*;
+use crate::world::Scene;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+// Renders frames one after another, calling scene_fn with each frame's start time in seconds
+// to build its camera and scene. Frame n is written to path_template with "{}" replaced by
+// n zero padded to four digits, counting from 1.
+//
+// Unless scene_fn sets its own shutter, ray time runs from 0 to 1 over a frame, so the shutter
+// stays open for the whole frame and moving objects should be given their displacement over
+// one frame (velocity / fps).
+pub fn render_animation<F>(
+    frames: u32,
+    fps: f64,
+    path_template: &str,
+    mut scene_fn: F,
+) -> io::Result<()>
+where
+    F: FnMut(f64) -> (Camera, Scene),
+{
+    if !path_template.contains("{}") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "animation path {} has no {{}} for the frame number",
+                path_template
+            ),
+        ));
+    }
+
+    for frame in 0..frames {
+        let time = frame as f64 / fps;
+        let (mut camera, scene) = scene_fn(time);
+
+        let path = path_template.replace("{}", &format!("{:04}", frame + 1));
+        camera.set_output_format(OutputFormat::from_path(&path));
+        if camera.shutter().is_none() {
+            camera.set_shutter(0.0, 1.0);
+        }
+
+        let file = File::create(&path)?;
+        camera.render_to(&scene, BufWriter::new(file))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BvhTree;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::testing::*;
+    use crate::vec3::*;
+
+    // One frame of a sphere moving along x by velocity over the frame's ray time.
+    fn render_frame(velocity: f64, shutter: Option<(f64, f64)>, name: &str) -> Vec<u8> {
+        let path = temp_path(&format!("{name}-{{}}.ppm"));
+        let path = path.to_str().unwrap().to_string();
+        render_animation(1, 24.0, &path, |_| {
+            let motion = Ray::new(
+                Point3::new(0.0, 0.0, -2.0),
+                Vec3::new(velocity, 0.0, 0.0),
+                0.0,
+            );
+            let material = Lambertian::as_arc_from_colour(Colour::new(0.6, 0.4, 0.3));
+            let mut world = BvhTree::new();
+            world.add(Box::new(Sphere::new(motion, 0.5, material)));
+            world.build();
+
+            let mut camera = Camera::builder()
+                .dimensions(16, 16)
+                .look_from(Point3::new(0.0, 0.0, 0.0))
+                .look_at(Point3::new(0.0, 0.0, -1.0))
+                .samples_per_pixel(8)
+                .build()
+                .unwrap();
+            camera.set_seed(3);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_progress_callback(|_, _| {});
+            if let Some((open, close)) = shutter {
+                camera.set_shutter(open, close);
+            }
+            (camera, Scene::new(world))
+        })
+        .unwrap();
+
+        let frame = path.replace("{}", "0001");
+        let bytes = std::fs::read(&frame).unwrap();
+        std::fs::remove_file(&frame).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn a_shutter_set_by_the_scene_is_kept() {
+        let still = render_frame(0.0, None, "still");
+        assert_eq!(render_frame(1.0, Some((0.0, 0.0)), "closed"), still);
+        assert_ne!(render_frame(1.0, None, "blurred"), still);
+    }
+
+    #[test]
+    fn each_frame_shows_the_sphere_where_it_is_at_that_frame_time() {
+        let path = temp_path("moving-{}.ppm");
+        let path = path.to_str().unwrap().to_string();
+        render_animation(3, 1.0, &path, |time| {
+            // Half a unit to the right per second, starting left of centre.
+            let center = Point3::new(-0.5 + 0.5 * time, 0.0, -2.0);
+            let material = Lambertian::as_arc_from_colour(Colour::new(0.6, 0.4, 0.3));
+            let mut world = BvhTree::new();
+            world.add(sphere(center, 0.4, material));
+            world.build();
+
+            let mut camera = test_camera(24, 16, 4);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_shutter(0.0, 0.0);
+            (camera, Scene::new(world))
+        })
+        .unwrap();
+
+        // The mean column of the pixels showing the sphere, which is red where the sky is blue.
+        let positions: Vec<f64> = (1..=3)
+            .map(|frame| {
+                let frame = path.replace("{}", &format!("{frame:04}"));
+                let image = image::open(&frame).unwrap().into_rgb8();
+                std::fs::remove_file(&frame).unwrap();
+                let columns: Vec<f64> = image
+                    .enumerate_pixels()
+                    .filter(|(_, _, pixel)| pixel[0] > pixel[2])
+                    .map(|(x, _, _)| x as f64)
+                    .collect();
+                assert!(!columns.is_empty(), "no sphere in {frame}");
+                columns.iter().sum::<f64>() / columns.len() as f64
+            })
+            .collect();
+
+        assert!(
+            positions.windows(2).all(|pair| pair[1] - pair[0] > 2.0),
+            "sphere columns {positions:?}"
+        );
+    }
+}
@@ -1,11 +1,10 @@
 use core::f64;
-use std::f64::{INFINITY, NEG_INFINITY};
 use std::ops::{Add, AddAssign};
 
 use crate::ray::*;
 use crate::vec3::*;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct BoundingBox {
     lower: Point3,
     upper: Point3,
@@ -18,39 +17,8 @@ pub struct IntersectionRecord {
 
 impl BoundingBox {
     pub fn new(a: Point3, b: Point3) -> BoundingBox {
-        let upper_x = if a.axis(0) > b.axis(0) {
-            a.axis(0)
-        } else {
-            b.axis(0)
-        };
-        let upper_y = if a.axis(1) > b.axis(1) {
-            a.axis(1)
-        } else {
-            b.axis(1)
-        };
-        let upper_z = if a.axis(2) > b.axis(2) {
-            a.axis(2)
-        } else {
-            b.axis(2)
-        };
-        let lower_x = if a.axis(0) < b.axis(0) {
-            a.axis(0)
-        } else {
-            b.axis(0)
-        };
-        let lower_y = if a.axis(1) < b.axis(1) {
-            a.axis(1)
-        } else {
-            b.axis(1)
-        };
-        let lower_z = if a.axis(2) < b.axis(2) {
-            a.axis(2)
-        } else {
-            b.axis(2)
-        };
-
-        let upper = Point3::new(upper_x, upper_y, upper_z);
-        let lower = Point3::new(lower_x, lower_y, lower_z);
+        let upper = a.most_maximum(b);
+        let lower = a.most_minimum(b);
 
         let mut out = BoundingBox { lower, upper };
 
@@ -66,6 +34,14 @@ impl BoundingBox {
         }
     }
 
+    pub fn lower(&self) -> Point3 {
+        self.lower
+    }
+
+    pub fn upper(&self) -> Point3 {
+        self.upper
+    }
+
     pub fn longest_axis(&self) -> usize {
         let lengths = [
             self.axis_length(0),
@@ -76,7 +52,7 @@ impl BoundingBox {
         if let Some((idx, _)) = lengths
             .iter()
             .enumerate()
-            .max_by(|(_idx1, val1), (_idx2, val2)| val1.partial_cmp(val2).unwrap())
+            .max_by(|(_idx1, val1), (_idx2, val2)| val1.total_cmp(val2))
         {
             idx
         } else {
@@ -101,6 +77,27 @@ impl BoundingBox {
         f64::abs(upper - lower)
     }
 
+    // True for BoundingBox::empty() and anything else that's inside out on some axis.
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.upper.axis(axis) < self.lower.axis(axis))
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let x = self.axis_length(0);
+        let y = self.axis_length(1);
+        let z = self.axis_length(2);
+
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn centroid(&self, axis: usize) -> f64 {
+        (self.lower.axis(axis) + self.upper.axis(axis)) / 2.0
+    }
+
     pub fn box_between(a: &BoundingBox, b: &BoundingBox) -> BoundingBox {
         let lower = a.lower.most_minimum(b.lower);
         let upper = a.upper.most_maximum(b.upper);
@@ -109,8 +106,8 @@ impl BoundingBox {
     }
 
     pub fn rotate_y(&self, cos_theta: f64, sin_theta: f64) -> BoundingBox {
-        let mut min = Point3::new(INFINITY, INFINITY, INFINITY);
-        let mut max = Point3::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
 
         for i in 0..2 {
             for j in 0..2 {
@@ -125,16 +122,16 @@ impl BoundingBox {
                     let test = Vec3::new(newx, y, newz);
 
                     for c in 0..3 {
-                        min.modify_axis(c, |val| f64::min(val, test.axis(c)));
-                        max.modify_axis(c, |val| f64::max(val, test.axis(c)));
+                        min[c] = f64::min(min[c], test[c]);
+                        max[c] = f64::max(max[c], test[c]);
                     }
                 }
             }
         }
 
         BoundingBox {
-            lower: min,
-            upper: max,
+            lower: Point3::from(min),
+            upper: Point3::from(max),
         }
     }
 
@@ -149,25 +146,28 @@ impl BoundingBox {
             let ax_min = self.lower.axis(axis);
             let ax_max = self.upper.axis(axis);
 
+            // A ray parallel to this slab never enters or leaves it, so it either stays inside
+            // for its whole length or misses. Dividing by zero here would give inf * 0 = NaN for
+            // an origin on the boundary, as happens with flat axis aligned quads.
+            if direction.axis(axis) == 0.0 {
+                if origin.axis(axis) < ax_min || origin.axis(axis) > ax_max {
+                    return None;
+                }
+                continue;
+            }
+
             let adinv = 1.0 / direction.axis(axis);
 
             let t0 = (ax_min - origin.axis(axis)) * adinv;
             let t1 = (ax_max - origin.axis(axis)) * adinv;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
 
-            if t0 < t1 {
-                if t0 > t_min {
-                    tmin_out = t0;
-                }
-                if t1 < t_max {
-                    tmax_out = t1;
-                }
-            } else {
-                if t1 > t_min {
-                    tmin_out = t1;
-                }
-                if t0 < t_max {
-                    tmax_out = t0;
-                }
+            // Each slab can only narrow the interval left by the previous axes.
+            if near > tmin_out {
+                tmin_out = near;
+            }
+            if far < tmax_out {
+                tmax_out = far;
             }
 
             if tmax_out <= tmin_out {
@@ -239,3 +239,81 @@ impl Add<BoundingBox> for Vec3 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn unit_box() -> BoundingBox {
+        BoundingBox::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn a_ray_through_the_box_hits_across_every_slab() {
+        let ray = Ray::new(Point3::new(-1.0, 1.6, 0.5), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        let hit = unit_box().intersects(&ray, 0.0, f64::INFINITY).unwrap();
+        assert_eq!((hit.tmin, hit.tmax), (1.0, 1.6));
+    }
+
+    #[test]
+    fn a_ray_passing_beside_a_corner_misses() {
+        // Inside the x slab for t in 2..3 but the y slab only for t in 0.5..1.5.
+        let ray = Ray::new(Point3::new(-2.0, 1.5, 0.5), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        assert!(unit_box().intersects(&ray, 0.0, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn axis_parallel_rays_hit_a_flat_box_from_the_front_only() {
+        // Padded to a sliver on y, like the box of an axis aligned floor quad.
+        let floor = BoundingBox::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 1.0));
+
+        let straight_down = Ray::new(Point3::new(0.5, 2.0, 0.5), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = floor
+            .intersects(&straight_down, 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.tmin - 2.0).abs() < 1e-3);
+
+        let level = Ray::new(Point3::new(-1.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(floor.intersects(&level, 0.0, f64::INFINITY).is_some());
+        let just_above = Ray::new(Point3::new(-1.0, 0.01, 0.5), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(floor.intersects(&just_above, 0.0, f64::INFINITY).is_none());
+
+        // Exactly on the padded boundary, where dividing by the zero component gave NaN.
+        let on_edge = Ray::new(floor.lower(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(floor.intersects(&on_edge, 0.0, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn axis_aligned_quads_in_a_bvh_are_hit_straight_on_but_not_skimmed() {
+        use crate::bvh::BvhTree;
+        use crate::hittable::Hittable;
+        use crate::quad::Quad;
+
+        let grey = grey();
+        let floor = Quad::boxed(
+            Point3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            grey.clone(),
+        );
+        let wall = Quad::boxed(
+            Point3::new(-1.0, 0.0, -3.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey,
+        );
+        let world = BvhTree::from_objects(vec![floor, wall]);
+
+        let down = Ray::new(Point3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = world.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+
+        let forward = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = world.hit(&forward, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+
+        let skimming = Ray::new(Point3::new(-3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(world.hit(&skimming, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -1,9 +1,27 @@
-use std::str::MatchIndices;
-
 use crate::bounding::*;
 use crate::hittable::*;
 use crate::ray::*;
-use crate::vec3::*;
+
+// Subtrees with fewer objects than this aren't worth handing to another rayon worker.
+const PARALLEL_BUILD_THRESHOLD: usize = 1024;
+
+// The traversal stack holds at most one pending node per level plus one. Median splits keep the
+// depth at log2 of the object count, so this covers far more objects than fit in memory. Deeper
+// trees still traverse correctly, spilling onto a fresh stack when this one fills.
+const MAX_TRAVERSAL_DEPTH: usize = 64;
+
+// Centroids are bucketed into this many bins per axis when evaluating SAH split candidates.
+const SAH_BINS: usize = 12;
+
+// SAH can produce lopsided trees, past this depth the builder falls back to median splits so
+// the tree always fits the fixed traversal stack.
+const SAH_MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SplitMethod {
+    Median,
+    Sah,
+}
 
 pub struct BvhTree {
     hittables: Vec<Box<dyn Hittable>>,
@@ -21,6 +39,7 @@ pub enum BvhSlab {
     Node {
         parent_index: usize,
         bounds: BoundingBox,
+        axis: usize,
 
         left_index: usize,
 
@@ -29,81 +48,111 @@ pub enum BvhSlab {
 }
 
 impl BvhSlab {
+    // Walks the tree with an explicit stack rather than recursing. Children are pushed far
+    // side first so the one nearer along the split axis is visited first, which lets the
+    // closest hit found so far cull more of the remaining boxes.
+    //
+    // Nothing here writes to the tree, the stack lives on the caller's thread, and every
+    // Hittable is Send + Sync. A built tree can be shared behind an Arc and hit from any number
+    // of threads at once.
     pub fn traverse(
         nodes: &[BvhSlab],
-        node_index: usize,
         r: &Ray,
         t_min: f64,
         t_max: f64,
         objects: &[Box<dyn Hittable>],
     ) -> Option<HitRecord> {
-        match &nodes[node_index] {
-            BvhSlab::Node {
-                parent_index,
-                bounds,
-                left_index,
-                right_index,
-            } => {
-                if let Some(intersection) = bounds.intersects(r, t_min, t_max) {
-                    let left_hit = BvhSlab::traverse(
-                        nodes,
-                        *left_index,
-                        r,
-                        intersection.tmin,
-                        intersection.tmax,
-                        objects,
-                    );
-                    let right_hit = BvhSlab::traverse(
-                        nodes,
-                        *right_index,
-                        r,
-                        intersection.tmin,
-                        intersection.tmax,
-                        objects,
-                    );
+        // An empty or not yet built tree has no root to start from.
+        if nodes.is_empty() {
+            return None;
+        }
+
+        BvhSlab::traverse_from(nodes, 0, r, t_min, t_max, objects)
+    }
+
+    fn traverse_from(
+        nodes: &[BvhSlab],
+        root: usize,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        objects: &[Box<dyn Hittable>],
+    ) -> Option<HitRecord> {
+        let mut stack = [root; MAX_TRAVERSAL_DEPTH];
+        let mut stack_len = 1;
 
-                    match (left_hit, right_hit) {
-                        (Some(a), Some(b)) => {
-                            if a.t < b.t {
-                                return Some(a);
-                            } else {
-                                return Some(b);
-                            }
+        let mut closest_so_far = t_max;
+        let mut record = None;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+
+            match &nodes[stack[stack_len]] {
+                BvhSlab::Node {
+                    bounds,
+                    axis,
+                    left_index,
+                    right_index,
+                    ..
+                } => {
+                    if bounds.intersects(r, t_min, closest_so_far).is_none() {
+                        continue;
+                    }
+
+                    let (near, far) = if r.direction().axis(*axis) < 0.0 {
+                        (*right_index, *left_index)
+                    } else {
+                        (*left_index, *right_index)
+                    };
+
+                    // A tree deeper than the stack walks the near child on a fresh stack of
+                    // its own, leaving the slot just popped free for the far one.
+                    if stack_len + 2 > MAX_TRAVERSAL_DEPTH {
+                        if let Some(hit) =
+                            BvhSlab::traverse_from(nodes, near, r, t_min, closest_so_far, objects)
+                        {
+                            closest_so_far = hit.t;
+                            record = Some(hit);
                         }
-                        (Some(a), None) => return Some(a),
-                        (None, Some(b)) => return Some(b),
-                        (None, None) => return None,
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        continue;
+                    }
+
+                    stack[stack_len] = far;
+                    stack[stack_len + 1] = near;
+                    stack_len += 2;
+                }
+                BvhSlab::Leaf { shape_index, .. } => {
+                    if let Some(hit) = objects[*shape_index].hit(r, t_min, closest_so_far) {
+                        closest_so_far = hit.t;
+                        record = Some(hit);
                     }
-                } else {
-                    return None;
                 }
-            }
-            BvhSlab::Leaf {
-                parent_index,
-                shape_index,
-            } => {
-                return objects[*shape_index].hit(r, t_min, t_max);
             }
         }
+
+        record
     }
 
+    // nodes holds exactly this subtree's 2n - 1 slots, the first of which lives at index in the
+    // full array. Left and right subtrees own disjoint halves of the remaining slots, so large
+    // ones are built in parallel without changing the layout a serial build would produce.
     fn recurse_nodes(
         objs_list: &mut [Box<dyn Hittable>],
         indicies: &mut [usize],
-        nodes: &mut Vec<BvhSlab>,
+        nodes: &mut [BvhSlab],
         index: usize,
+        method: SplitMethod,
+        depth: usize,
     ) {
         let len = objs_list.len();
-        let mid = len / 2;
 
         if len == 1 {
-            nodes.insert(
-                index,
-                BvhSlab::Leaf {
-                    parent_index: index,
-                    shape_index: indicies[0],
-                },
-            );
+            nodes[0] = BvhSlab::Leaf {
+                parent_index: index,
+                shape_index: indicies[0],
+            };
             return;
         }
 
@@ -112,46 +161,167 @@ impl BvhSlab {
             bbox = BoundingBox::box_between(&bbox, obj.bounding_box());
         });
 
+        let sah_split = if method == SplitMethod::Sah && depth < SAH_MAX_DEPTH {
+            BvhSlab::sah_split(objs_list)
+        } else {
+            None
+        };
+        let (axis, mid) = sah_split.unwrap_or((bbox.longest_axis(), len / 2));
+
+        // Sorting the slice in place keeps each object at the position its index refers to,
+        // so the split groups objects that are spatially close along this node's axis.
+        objs_list.sort_by(|obj1, obj2| {
+            BvhSlab::sort_key(obj1.as_ref(), axis)
+                .total_cmp(&BvhSlab::sort_key(obj2.as_ref(), axis))
+        });
+
         let (left_objects, right_objects) = objs_list.split_at_mut(mid);
         let (left_indicies, right_indicies) = indicies.split_at_mut(mid);
 
         let left_len = left_indicies.len() * 2 - 1;
 
-        nodes.insert(
-            index,
-            BvhSlab::Node {
-                parent_index: index,
-                bounds: bbox,
-                left_index: index + 1,
-                right_index: index + 1 + left_len,
-            },
-        );
+        nodes[0] = BvhSlab::Node {
+            parent_index: index,
+            bounds: bbox,
+            axis,
+            left_index: index + 1,
+            right_index: index + 1 + left_len,
+        };
 
-        BvhSlab::recurse_nodes(left_objects, left_indicies, nodes, index + 1);
-        BvhSlab::recurse_nodes(right_objects, right_indicies, nodes, index + 1 + left_len);
+        let (left_nodes, right_nodes) = nodes[1..].split_at_mut(left_len);
+
+        if len >= PARALLEL_BUILD_THRESHOLD {
+            rayon::join(
+                || {
+                    BvhSlab::recurse_nodes(
+                        left_objects,
+                        left_indicies,
+                        left_nodes,
+                        index + 1,
+                        method,
+                        depth + 1,
+                    )
+                },
+                || {
+                    BvhSlab::recurse_nodes(
+                        right_objects,
+                        right_indicies,
+                        right_nodes,
+                        index + 1 + left_len,
+                        method,
+                        depth + 1,
+                    )
+                },
+            );
+        } else {
+            BvhSlab::recurse_nodes(
+                left_objects,
+                left_indicies,
+                left_nodes,
+                index + 1,
+                method,
+                depth + 1,
+            );
+            BvhSlab::recurse_nodes(
+                right_objects,
+                right_indicies,
+                right_nodes,
+                index + 1 + left_len,
+                method,
+                depth + 1,
+            );
+        }
     }
 
-    pub fn build_nodes(list: &mut [Box<dyn Hittable>]) -> Vec<BvhSlab> {
-        let mut bbox = BoundingBox::empty();
-        list.iter().for_each(|obj| {
-            bbox = BoundingBox::box_between(&bbox, obj.bounding_box());
-        });
+    // Centroid along axis, except that empty boxes, whose centroid is NaN, sort after
+    // everything else.
+    fn sort_key(obj: &dyn Hittable, axis: usize) -> f64 {
+        let bounds = obj.bounding_box();
+        if bounds.is_empty() {
+            f64::INFINITY
+        } else {
+            bounds.centroid(axis)
+        }
+    }
 
-        let axis = bbox.longest_axis();
+    // Bins object centroids along each axis and returns the axis and number of objects to put
+    // in the left child that minimise the surface area cost of the split. None means every
+    // centroid coincides and the caller should fall back to a median split.
+    fn sah_split(objs_list: &[Box<dyn Hittable>]) -> Option<(usize, usize)> {
+        let mut best: Option<(f64, usize, usize)> = None;
 
-        list.sort_by(|obj1, obj2| {
-            obj1.bounding_box()
-                .axis_length(axis)
-                .partial_cmp(&obj2.bounding_box().axis_length(axis))
-                .expect("Couldn't compare bounding boxes of objects to sort")
-        });
+        for axis in 0..3 {
+            let (min, max) = objs_list
+                .iter()
+                .filter(|obj| !obj.bounding_box().is_empty())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), obj| {
+                    let centroid = obj.bounding_box().centroid(axis);
+                    (min.min(centroid), max.max(centroid))
+                });
+
+            let extent = max - min;
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut counts = [0usize; SAH_BINS];
+            let mut bounds: [BoundingBox; SAH_BINS] = std::array::from_fn(|_| BoundingBox::empty());
+
+            // Empty boxes land in the last bin, matching where the sort puts them.
+            for obj in objs_list {
+                let offset = (BvhSlab::sort_key(obj.as_ref(), axis) - min) / extent;
+                let bin = ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1);
+                counts[bin] += 1;
+                bounds[bin] = BoundingBox::box_between(&bounds[bin], obj.bounding_box());
+            }
+
+            // Sweep from the right so each candidate split can read the cost of its right side.
+            let mut right_areas = [0.0; SAH_BINS];
+            let mut right_box = BoundingBox::empty();
+            for bin in (1..SAH_BINS).rev() {
+                right_box = BoundingBox::box_between(&right_box, &bounds[bin]);
+                right_areas[bin] = right_box.surface_area();
+            }
+
+            let mut left_box = BoundingBox::empty();
+            let mut left_count = 0;
+            for bin in 0..SAH_BINS - 1 {
+                left_box = BoundingBox::box_between(&left_box, &bounds[bin]);
+                left_count += counts[bin];
+
+                let right_count = objs_list.len() - left_count;
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let cost = left_box.surface_area() * left_count as f64
+                    + right_areas[bin + 1] * right_count as f64;
+
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, left_count));
+                }
+            }
+        }
+
+        best.map(|(_, axis, left_count)| (axis, left_count))
+    }
+
+    fn build_nodes(list: &mut [Box<dyn Hittable>], method: SplitMethod) -> Vec<BvhSlab> {
+        if list.is_empty() {
+            return Vec::new();
+        }
 
-        let mut vec: Vec<BvhSlab> = Vec::new();
-        vec.reserve((list.len() * 2) - 1);
+        // Every slot is overwritten by recurse_nodes, the placeholder is never traversed.
+        let mut vec: Vec<BvhSlab> = (0..(list.len() * 2) - 1)
+            .map(|_| BvhSlab::Leaf {
+                parent_index: 0,
+                shape_index: 0,
+            })
+            .collect();
 
         let mut indicies: Vec<usize> = (0..list.len()).collect();
 
-        BvhSlab::recurse_nodes(list, &mut indicies, &mut vec, 0);
+        BvhSlab::recurse_nodes(list, &mut indicies, &mut vec, 0, method, 0);
 
         vec
     }
@@ -171,19 +341,463 @@ impl BvhTree {
         let bounds = BoundingBox::box_between(&self.bounds, object.bounding_box());
         self.bounds = bounds;
         self.hittables.push(object);
+    }
+
+    pub fn from_objects(objects: Vec<Box<dyn Hittable>>) -> BvhTree {
+        let mut tree = BvhTree::new();
+        objects.into_iter().for_each(|object| tree.add(object));
+        tree.build();
+        tree
+    }
+
+    // Objects added after the last build aren't visible to traversal until this is called again.
+    pub fn build(&mut self) {
+        self.nodes = BvhSlab::build_nodes(&mut self.hittables, SplitMethod::Median);
+    }
+
+    // Slower to build than the median split, but gives noticeably tighter trees for scenes
+    // where objects are clustered unevenly.
+    pub fn build_sah(&mut self) {
+        self.nodes = BvhSlab::build_nodes(&mut self.hittables, SplitMethod::Sah);
+    }
+
+    // Covers every object added so far, built or not.
+    pub fn bounds(&self) -> &BoundingBox {
+        &self.bounds
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.hittables.len()
+    }
+
+    // Leaves and interior nodes together, zero until the tree has been built.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Building reorders the objects, so this follows leaf order rather than insertion order.
+    pub fn iter_objects(&self) -> impl Iterator<Item = &Box<dyn Hittable>> {
+        self.hittables.iter()
+    }
+}
 
-        let nodes = BvhSlab::build_nodes(&mut self.hittables);
-        println!("{:?}", nodes);
-        self.nodes = nodes;
+impl Default for BvhTree {
+    fn default() -> Self {
+        BvhTree::new()
     }
 }
 
 impl Hittable for BvhTree {
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
-        BvhSlab::traverse(&self.nodes, 0, r, ray_tmin, ray_tmax, &self.hittables)
+        BvhSlab::traverse(&self.nodes, r, ray_tmin, ray_tmax, &self.hittables)
     }
 
     fn bounding_box(&self) -> &BoundingBox {
         &self.bounds
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::vec3::*;
+
+    fn ball(x: f64, y: f64, z: f64) -> Box<dyn Hittable> {
+        ball_at(Point3::new(x, y, z))
+    }
+
+    fn ball_at(center: Point3) -> Box<dyn Hittable> {
+        sphere(center, 0.4, grey())
+    }
+
+    // Positions in hittables of every object under the node at index.
+    fn leaves(tree: &BvhTree, index: usize) -> Vec<usize> {
+        match &tree.nodes[index] {
+            BvhSlab::Leaf { shape_index, .. } => vec![*shape_index],
+            BvhSlab::Node {
+                left_index,
+                right_index,
+                ..
+            } => {
+                let mut found = leaves(tree, *left_index);
+                found.extend(leaves(tree, *right_index));
+                found
+            }
+        }
+    }
+
+    #[test]
+    fn objects_along_x_split_into_left_and_right_halves() {
+        let mut tree = BvhTree::new();
+        for x in [5.0, 1.0, 7.0, 3.0, 0.0, 6.0, 2.0, 4.0] {
+            tree.add(ball(x, 0.0, 0.0));
+        }
+        tree.build();
+
+        let BvhSlab::Node {
+            left_index,
+            right_index,
+            ..
+        } = tree.nodes[0]
+        else {
+            panic!("root of eight objects should be a node");
+        };
+        let x_of = |index: usize| tree.hittables[index].bounding_box().centroid(0);
+        let left_max = leaves(&tree, left_index)
+            .into_iter()
+            .map(x_of)
+            .fold(f64::MIN, f64::max);
+        let right_min = leaves(&tree, right_index)
+            .into_iter()
+            .map(x_of)
+            .fold(f64::MAX, f64::min);
+
+        assert_eq!(left_max, 3.0);
+        assert_eq!(right_min, 4.0);
+    }
+
+    #[test]
+    fn empty_objects_next_to_others_build_without_panicking() {
+        for sah in [false, true] {
+            let mut tree = BvhTree::new();
+            tree.add(Box::new(HittableList::new()));
+            tree.add(ball(0.0, 0.0, -3.0));
+            tree.add(Box::new(HittableList::new()));
+            tree.add(ball(2.0, 0.0, -3.0));
+            if sah {
+                tree.build_sah();
+            } else {
+                tree.build();
+            }
+
+            let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let hit = tree.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!((hit.t - 2.6).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn an_empty_tree_reports_no_hit() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut tree = BvhTree::new();
+        assert!(tree.hit(&ray, 0.001, f64::INFINITY).is_none());
+        tree.build();
+        assert!(tree.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn threads_sharing_a_tree_see_the_same_hits_as_one_thread() {
+        use rand::rngs::SmallRng;
+        use rand::{Rng, SeedableRng};
+        use std::sync::Arc;
+
+        let mut tree = BvhTree::new();
+        for i in 0..64 {
+            let (x, y) = ((i % 8) as f64 - 3.5, (i / 8) as f64 - 3.5);
+            tree.add(ball(x, y, -5.0 - (i % 3) as f64));
+        }
+        tree.build_sah();
+        let tree = Arc::new(tree);
+
+        let mut rng = SmallRng::seed_from_u64(86);
+        let rays: Arc<Vec<Ray>> = Arc::new(
+            (0..5000)
+                .map(|_| {
+                    let direction = Vec3::new(
+                        rng.random_range(-0.8..0.8),
+                        rng.random_range(-0.8..0.8),
+                        -1.0,
+                    );
+                    Ray::new(Point3::new(0.0, 0.0, 0.0), direction, 0.0)
+                })
+                .collect(),
+        );
+        let trace = |tree: &BvhTree, rays: &[Ray]| -> Vec<Option<(f64, Vec3)>> {
+            rays.iter()
+                .map(|ray| {
+                    tree.hit(ray, 0.001, f64::INFINITY)
+                        .map(|hit| (hit.t, hit.normal()))
+                })
+                .collect()
+        };
+
+        let serial = trace(&tree, &rays);
+        assert!(serial.iter().filter(|hit| hit.is_some()).count() > 1000);
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (tree, rays) = (Arc::clone(&tree), Arc::clone(&rays));
+                std::thread::spawn(move || trace(&tree, &rays))
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap() == serial);
+        }
+    }
+
+    #[test]
+    fn a_thousand_object_tree_finds_the_same_hits_as_a_flat_list() {
+        use rand::rngs::SmallRng;
+        use rand::{Rng, SeedableRng};
+        use std::time::{Duration, Instant};
+
+        let mut rng = SmallRng::seed_from_u64(12);
+        let centers: Vec<Point3> = (0..1000)
+            .map(|_| {
+                Point3::new(
+                    rng.random_range(-20.0..20.0),
+                    rng.random_range(-20.0..20.0),
+                    rng.random_range(-60.0..-10.0),
+                )
+            })
+            .collect();
+
+        let start = Instant::now();
+        let tree = BvhTree::from_objects(centers.iter().map(|&c| ball_at(c)).collect());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        let mut list = HittableList::new();
+        centers.iter().for_each(|&c| list.add(ball_at(c)));
+
+        for _ in 0..2000 {
+            let direction = Vec3::new(
+                rng.random_range(-1.0..1.0),
+                rng.random_range(-1.0..1.0),
+                -1.0,
+            );
+            let ray = ray(Point3::new(0.0, 0.0, 0.0), direction);
+            let from_tree = tree.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            let from_list = list.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t);
+            assert_eq!(from_tree, from_list);
+        }
+    }
+
+    #[test]
+    fn serial_and_parallel_builds_give_the_same_layout() {
+        let count = 3 * PARALLEL_BUILD_THRESHOLD;
+        let build_with = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| {
+                    BvhTree::from_objects(
+                        (0..count)
+                            .map(|i| {
+                                let i = i as f64;
+                                ball((i * 7.31) % 50.0, (i * 3.17) % 20.0, -10.0 - i % 13.0)
+                            })
+                            .collect(),
+                    )
+                })
+        };
+
+        // With one worker rayon::join runs both halves in turn, the same as a serial build.
+        let (serial, parallel) = (build_with(1), build_with(8));
+        assert_eq!(
+            format!("{:?}", serial.nodes),
+            format!("{:?}", parallel.nodes)
+        );
+        let order = |tree: &BvhTree| -> Vec<f64> {
+            tree.iter_objects()
+                .map(|obj| obj.bounding_box().centroid(0))
+                .collect()
+        };
+        assert_eq!(order(&serial), order(&parallel));
+
+        for k in 0..50 {
+            let ray = ray(Point3::new(k as f64, 5.0, 0.0), Vec3::new(0.1, -0.05, -1.0));
+            assert_eq!(
+                serial.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t),
+                parallel.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t)
+            );
+        }
+    }
+
+    #[test]
+    fn a_thousand_spheres_in_a_line_give_the_nearest_hit() {
+        let centers: Vec<Point3> = (0..1000)
+            .map(|i| Point3::new(0.0, 0.0, -2.0 - i as f64))
+            .collect();
+        let tree = BvhTree::from_objects(centers.iter().map(|&c| ball_at(c)).collect());
+        let mut list = HittableList::new();
+        centers.iter().for_each(|&c| list.add(ball_at(c)));
+
+        let along = ray(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = tree.hit(&along, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 1.6).abs() < 1e-9);
+
+        let from_behind = ray(Point3::new(0.0, 0.0, -1100.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = tree.hit(&from_behind, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 98.6).abs() < 1e-9);
+
+        for k in 0..100 {
+            let ray = ray(
+                Point3::new(0.3, 0.0, 0.0),
+                Vec3::new(-0.0003 * k as f64, 0.0, -1.0),
+            );
+            assert_eq!(
+                tree.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t),
+                list.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t)
+            );
+        }
+    }
+
+    #[test]
+    fn a_chain_deeper_than_the_traversal_stack_still_finds_the_nearest_hit() {
+        // Each node holds one sphere on the left and the rest of the chain on the right, the
+        // side a ray heading down -z visits first, so every level leaves one node pending.
+        let depth = 4 * MAX_TRAVERSAL_DEPTH;
+        let objects: Vec<Box<dyn Hittable>> = (0..=depth)
+            .map(|i| ball(0.0, 0.0, -2.0 - i as f64))
+            .collect();
+        let mut nodes = Vec::new();
+        for level in 0..depth {
+            let mut bounds = BoundingBox::empty();
+            for object in &objects[level..] {
+                bounds = BoundingBox::box_between(&bounds, object.bounding_box());
+            }
+            nodes.push(BvhSlab::Node {
+                parent_index: 0,
+                bounds,
+                axis: 2,
+                left_index: nodes.len() + 1,
+                right_index: nodes.len() + 2,
+            });
+            nodes.push(BvhSlab::Leaf {
+                parent_index: 0,
+                shape_index: level,
+            });
+        }
+        nodes.push(BvhSlab::Leaf {
+            parent_index: 0,
+            shape_index: depth,
+        });
+
+        let along = ray(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = BvhSlab::traverse(&nodes, &along, 0.001, f64::INFINITY, &objects).unwrap();
+        assert!((hit.t - 1.6).abs() < 1e-9);
+
+        let past_most = ray(Point3::new(0.0, 0.0, -150.5), Vec3::new(0.0, 0.0, -1.0));
+        let hit = BvhSlab::traverse(&nodes, &past_most, 0.001, f64::INFINITY, &objects).unwrap();
+        assert!((hit.t - 0.1).abs() < 1e-9);
+    }
+
+    // Nodes the traversal reaches for this ray, in the same near-first order, counting both
+    // those whose box is tested and leaves whose object is.
+    fn visits(tree: &BvhTree, index: usize, r: &Ray, closest: &mut f64) -> usize {
+        match &tree.nodes[index] {
+            BvhSlab::Node {
+                bounds,
+                axis,
+                left_index,
+                right_index,
+                ..
+            } => {
+                if bounds.intersects(r, 0.001, *closest).is_none() {
+                    return 1;
+                }
+                let (near, far) = if r.direction().axis(*axis) < 0.0 {
+                    (*right_index, *left_index)
+                } else {
+                    (*left_index, *right_index)
+                };
+                1 + visits(tree, near, r, closest) + visits(tree, far, r, closest)
+            }
+            BvhSlab::Leaf { shape_index, .. } => {
+                if let Some(hit) = tree.hittables[*shape_index].hit(r, 0.001, *closest) {
+                    *closest = hit.t;
+                }
+                1
+            }
+        }
+    }
+
+    #[test]
+    fn sah_visits_fewer_nodes_than_median_on_a_clustered_scene() {
+        use rand::rngs::SmallRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = SmallRng::seed_from_u64(35);
+        let mut centers: Vec<Point3> = (0..300)
+            .map(|_| {
+                Point3::new(
+                    rng.random_range(-0.5..0.5),
+                    rng.random_range(-0.5..0.5),
+                    rng.random_range(-10.5..-9.5),
+                )
+            })
+            .collect();
+        centers.extend((0..60).map(|_| {
+            Point3::new(
+                rng.random_range(-30.0..30.0),
+                rng.random_range(-30.0..30.0),
+                rng.random_range(-60.0..-5.0),
+            )
+        }));
+        let build = |sah: bool| {
+            let mut tree = BvhTree::new();
+            centers
+                .iter()
+                .for_each(|&c| tree.add(sphere(c, 0.05, grey())));
+            if sah {
+                tree.build_sah();
+            } else {
+                tree.build();
+            }
+            tree
+        };
+        let (median, sah) = (build(false), build(true));
+
+        let rays: Vec<Ray> = (0..2000)
+            .map(|_| {
+                let direction = Vec3::new(
+                    rng.random_range(-0.6..0.6),
+                    rng.random_range(-0.6..0.6),
+                    -1.0,
+                );
+                ray(Point3::new(0.0, 0.0, 0.0), direction)
+            })
+            .collect();
+        let average_visits = |tree: &BvhTree| {
+            rays.iter()
+                .map(|r| {
+                    let mut closest = f64::INFINITY;
+                    visits(tree, 0, r, &mut closest)
+                })
+                .sum::<usize>() as f64
+                / rays.len() as f64
+        };
+
+        let (median_visits, sah_visits) = (average_visits(&median), average_visits(&sah));
+        assert!(
+            sah_visits < median_visits,
+            "SAH {sah_visits} against median {median_visits}"
+        );
+        for r in &rays {
+            assert_eq!(
+                median.hit(r, 0.001, f64::INFINITY).map(|hit| hit.t),
+                sah.hit(r, 0.001, f64::INFINITY).map(|hit| hit.t)
+            );
+        }
+    }
+
+    #[test]
+    fn a_tree_of_five_spheres_has_five_objects_and_nine_nodes() {
+        let mut tree = BvhTree::new();
+        for x in 0..5 {
+            tree.add(ball(x as f64, 0.0, -3.0));
+        }
+        assert_eq!((tree.object_count(), tree.node_count()), (5, 0));
+
+        tree.build();
+        assert_eq!(tree.object_count(), 5);
+        assert_eq!(tree.node_count(), 9);
+
+        let mut xs: Vec<f64> = tree
+            .iter_objects()
+            .map(|obj| obj.bounding_box().centroid(0))
+            .collect();
+        xs.sort_by(f64::total_cmp);
+        assert_eq!(xs, [0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+}
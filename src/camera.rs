@@ -1,46 +1,271 @@
 use crate::bvh::BvhTree;
+use crate::environment::{EnvironmentMap, Sky};
+use crate::framebuffer::Framebuffer;
 use crate::hittable::*;
 use crate::ray::*;
 use crate::vec3::*;
+use crate::world::Scene;
 
-use indicatif::{MultiProgress, ProgressBar};
+use core::f64;
+use image::ExtendedColorType;
+use image::ImageEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::openexr::OpenExrEncoder;
+use image::codecs::png::PngEncoder;
+use indicatif::ProgressBar;
 use rand::prelude::*;
 use rayon::prelude::*;
 
-use std::fs::File;
-use std::io::Write;
-use std::io::{self, BufWriter};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor};
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Jpeg,
+    // Linear 32-bit float colour, without tone mapping, gamma correction or clamping.
+    Exr,
+}
+
+impl OutputFormat {
+    // Anything that isn't recognisably a PNG, JPEG or EXR falls back to PPM.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> OutputFormat {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => OutputFormat::Png,
+            Some("jpg") | Some("jpeg") => OutputFormat::Jpeg,
+            Some("exr") => OutputFormat::Exr,
+            _ => OutputFormat::Ppm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMap {
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, colour: Colour) -> Colour {
+        match self {
+            ToneMap::None => colour,
+            ToneMap::Reinhard => colour.map(|c| c / (1.0 + c)),
+            // Narkowicz's fit of the ACES filmic curve.
+            ToneMap::Aces => colour.map(|c| {
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+// Orthographic width is the extent of the view in world units, the height follows from the
+// image aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic { width: f64 },
+}
+
+// Reconstruction filter for the samples within a pixel. Offsets are drawn in proportion to the
+// filter's weight, in pixel units from the pixel centre, so every sample still counts equally.
+// Box covers the pixel, Tent reaches the centres of its neighbours and Gaussian spreads by
+// sigma pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFilter {
+    Box,
+    Tent,
+    Gaussian(f64),
+}
+
+impl PixelFilter {
+    // Maps a point in the unit square, uniform or stratified, to an offset under the filter.
+    fn warp(&self, x: f64, y: f64) -> (f64, f64) {
+        match *self {
+            PixelFilter::Box => (x - 0.5, y - 0.5),
+            PixelFilter::Tent => {
+                // Inverse of the tent's cumulative distribution, one axis at a time.
+                let tent = |u: f64| {
+                    if u < 0.5 {
+                        f64::sqrt(2.0 * u) - 1.0
+                    } else {
+                        1.0 - f64::sqrt(2.0 - 2.0 * u)
+                    }
+                };
+                (tent(x), tent(y))
+            }
+            // Box-Muller, x picks the radius and y the angle.
+            PixelFilter::Gaussian(sigma) => {
+                let radius = sigma * f64::sqrt(-2.0 * f64::ln(1.0 - x));
+                let angle = 2.0 * f64::consts::PI * y;
+                (radius * f64::cos(angle), radius * f64::sin(angle))
+            }
+        }
+    }
+}
+
+// What each camera ray returns. Beauty traces full paths, the rest stop at the first hit to
+// show one property of the surface there. Normals maps the shading normal from -1..1 into
+// 0..1 per channel, Albedo is the material's unlit base colour, and Depth fades from white at
+// the camera to black at the far side of the scene's bounds. Misses are black in all three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadingMode {
+    Beauty,
+    Normals,
+    Albedo,
+    Depth,
+}
+
+// Shape of the lens samples drawn for defocus blur, which is the shape out of focus highlights
+// take. Polygon is a regular n-gon with its corners on the circle, fewer than three sides
+// falls back to the circle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aperture {
+    Circle,
+    Polygon(u32),
+}
+
+impl Aperture {
+    // A point in the unit aperture, on the lens plane.
+    fn sample<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        match *self {
+            Aperture::Polygon(sides) if sides >= 3 => {
+                // Every blade triangle has the same area, so pick one and sample it uniformly.
+                let step = 2.0 * f64::consts::PI / sides as f64;
+                let blade = rng.random_range(0..sides) as f64;
+                let a = Vec3::new(f64::cos(blade * step), f64::sin(blade * step), 0.0);
+                let b = Vec3::new(
+                    f64::cos((blade + 1.0) * step),
+                    f64::sin((blade + 1.0) * step),
+                    0.0,
+                );
+
+                let (mut s, mut t) = (rng.random::<f64>(), rng.random::<f64>());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                s * a + t * b
+            }
+            _ => Vec3::random_in_unit_disk(rng),
+        }
+    }
+}
+
+// Counts from the most recent render. Bounces are the rays scattered after each camera ray,
+// not counting light sampling shadow rays.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    pub primary_rays: u64,
+    pub bounces: u64,
+    pub non_finite_samples: u64,
+    pub elapsed: Duration,
+}
+
+// Rays traced within one tile, merged into the camera's totals once the tile is done so
+// threads don't contend on the shared counters every sample.
+#[derive(Debug, Clone, Copy, Default)]
+struct RayCounts {
+    primary_rays: u64,
+    bounces: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x: u64,
+    y: u64,
+    width: u64,
+    height: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpointing {
+    path: PathBuf,
+    interval: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AdaptiveSampling {
+    min_samples: i32,
+    max_samples: i32,
+    tolerance: f64,
+}
 
 pub struct Camera {
     image_height: u64,
     image_width: u64,
     center: Point3,
+    look_at: Point3,
+    up_vec: Vec3,
+    vfov: f64,
+    projection: Projection,
+    w: Vec3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
-    aspect_ratio: f64,
     samples_per_pixel: i32,
-    sample_scale_factor: f64,
-    out_file: BufWriter<File>,
+    stratified: bool,
+    adaptive: Option<AdaptiveSampling>,
+    out_path: PathBuf,
+    output_format: OutputFormat,
+    tone_map: ToneMap,
+    gamma: f64,
     max_depth: u32,
-
-    vfov: f64,
-
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
+    sky_at_max_depth: bool,
+    russian_roulette: Option<u32>,
+    sample_clamp: Option<f64>,
+    non_finite_samples: AtomicU64,
+    primary_rays: AtomicU64,
+    bounces: AtomicU64,
+    render_time: Mutex<Duration>,
 
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
     focus_angle: f64,
+    focus_distance: f64,
+    aperture: Aperture,
+    pixel_filter: PixelFilter,
+    shading_mode: ShadingMode,
+
+    // None until set, which renders every ray at time 0.
+    shutter: Option<(f64, f64)>,
 
-    rng_src: Arc<Mutex<SmallRng>>,
     background: Colour,
+    environment: Option<EnvironmentMap>,
+    sky: Option<Sky>,
+
+    tile_size: u64,
+    region: Option<Tile>,
+    on_progress: Option<ProgressCallback>,
+    checkpoint: Option<Checkpointing>,
+    resume_path: Option<PathBuf>,
+    seed: Option<u64>,
 }
 
+// Called with the number of completed rows and the total row count.
+pub type ProgressCallback = Box<dyn Fn(u64, u64) + Send + Sync>;
+
 impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::new()
+    }
+
+    // Fails on a zero width, an aspect ratio or field of view that can't make an image, or a
+    // view direction that is zero or parallel to up.
+    #[allow(clippy::too_many_arguments)]
     pub fn new<P>(
         aspect_ratio: f64,
         image_width: u64,
@@ -51,191 +276,2306 @@ impl Camera {
         focus_distance: f64,
         focus_angle: f64,
         filename: P,
-    ) -> Result<Camera, io::Error>
+    ) -> Result<Camera, CameraError>
     where
         P: AsRef<Path>,
     {
+        if image_width == 0 || aspect_ratio.is_nan() || aspect_ratio <= 0.0 {
+            return Err(CameraError::ZeroDimension {
+                width: image_width,
+                aspect_ratio,
+            });
+        }
+
         let image_height: u64 = {
             let x = image_width as f64 / aspect_ratio;
-            if x < 1.0 {
-                1
-            } else {
-                x as u64
-            }
+            if x < 1.0 { 1 } else { x as u64 }
         };
 
-        // Default to 90 degree FOV at first
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-
-        let viewport_height: f64 = 2.0 * h * focus_distance;
-        let viewport_width: f64 = viewport_height * (image_width as f64 / image_height as f64);
-
-        let w = unit_vector(Vec3::from(center - look_at));
-        let u = unit_vector(cross(up_vec, w));
-        let v = cross(w, u);
-
-        let viewport_u = viewport_width * u;
-        let viewport_v = viewport_height * -v;
-
-        let pixel_delta_u = viewport_u / image_width as f64;
-        let pixel_delta_v = viewport_v / image_height as f64;
+        Camera::with_dimensions(
+            image_width,
+            image_height,
+            vfov,
+            center,
+            look_at,
+            up_vec,
+            focus_distance,
+            focus_angle,
+            filename,
+        )
+    }
 
-        let viewport_upper_left =
-            (center - (focus_distance * w)) - (viewport_u / 2.0) - (viewport_v / 2.0);
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+    // Like Camera::new but takes the exact pixel dimensions, the aspect ratio follows from them
+    // instead of the height being rounded down from the width.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_dimensions<P>(
+        image_width: u64,
+        image_height: u64,
+        vfov: f64,
+        center: Point3,
+        look_at: Point3,
+        up_vec: Vec3,
+        focus_distance: f64,
+        focus_angle: f64,
+        filename: P,
+    ) -> Result<Camera, CameraError>
+    where
+        P: AsRef<Path>,
+    {
+        if image_width == 0 || image_height == 0 {
+            return Err(CameraError::ZeroDimension {
+                width: image_width,
+                aspect_ratio: if image_height == 0 {
+                    0.0
+                } else {
+                    image_width as f64 / image_height as f64
+                },
+            });
+        }
+        if vfov.is_nan() || vfov <= 0.0 || vfov >= 180.0 {
+            return Err(CameraError::InvalidFov(vfov));
+        }
+        let view = Vec3::from(center - look_at);
+        if view.near_zero() || cross(up_vec, view).near_zero() {
+            return Err(CameraError::DegenerateView);
+        }
 
         let samples_per_pixel = 10;
-        let sample_scale_factor = 1.0 / samples_per_pixel as f64;
-        let file = File::create(filename)?;
-        let bufwriter = BufWriter::new(file);
-
-        let defocus_radius = focus_distance * (focus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
-        Ok(Camera {
+        let output_format = OutputFormat::from_path(&filename);
+
+        let mut camera = Camera {
             image_height,
             image_width,
             center,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            aspect_ratio,
+            look_at,
+            up_vec,
+            vfov,
+            projection: Projection::Perspective,
+            w: Vec3::default(),
+            pixel00_loc: Point3::default(),
+            pixel_delta_u: Vec3::default(),
+            pixel_delta_v: Vec3::default(),
             samples_per_pixel,
-            sample_scale_factor,
-            out_file: bufwriter,
+            stratified: false,
+            adaptive: None,
+            out_path: filename.as_ref().to_path_buf(),
+            output_format,
+            tone_map: ToneMap::None,
+            gamma: DEFAULT_GAMMA,
             max_depth: 10,
-            vfov,
-            u,
-            v,
-            w,
-            defocus_disk_u,
-            defocus_disk_v,
+            sky_at_max_depth: false,
+            russian_roulette: None,
+            sample_clamp: None,
+            non_finite_samples: AtomicU64::new(0),
+            primary_rays: AtomicU64::new(0),
+            bounces: AtomicU64::new(0),
+            render_time: Mutex::new(Duration::ZERO),
+            defocus_disk_u: Vec3::default(),
+            defocus_disk_v: Vec3::default(),
             focus_angle,
+            focus_distance,
+            aperture: Aperture::Circle,
+            pixel_filter: PixelFilter::Box,
+            shading_mode: ShadingMode::Beauty,
+
+            shutter: None,
 
-            rng_src: Arc::new(Mutex::new(SmallRng::from_os_rng())),
             background: Colour::new(0.0, 0.0, 0.0),
-        })
+            environment: None,
+            sky: None,
+
+            tile_size: 32,
+            region: None,
+            on_progress: None,
+            checkpoint: None,
+            resume_path: None,
+            seed: None,
+        };
+        camera.update_viewport();
+
+        Ok(camera)
+    }
+
+    // Recomputes the viewport and lens vectors from the current view parameters,
+    // must be called whenever one of them changes after construction.
+    fn update_viewport(&mut self) {
+        let aspect = self.image_width as f64 / self.image_height as f64;
+        let (viewport_width, viewport_height, viewport_distance) = match self.projection {
+            Projection::Perspective => {
+                let theta = self.vfov.to_radians();
+                let h = (theta / 2.0).tan();
+                let viewport_height = 2.0 * h * self.focus_distance;
+                (
+                    viewport_height * aspect,
+                    viewport_height,
+                    self.focus_distance,
+                )
+            }
+            // Parallel rays start on the viewport itself, so it passes through the eye point.
+            Projection::Orthographic { width } => (width, width / aspect, 0.0),
+        };
+
+        let w = unit_vector(Vec3::from(self.center - self.look_at));
+        self.w = w;
+        let u = unit_vector(cross(self.up_vec, w));
+        let v = cross(w, u);
+
+        let viewport_u = viewport_width * u;
+        let viewport_v = viewport_height * -v;
+
+        self.pixel_delta_u = viewport_u / self.image_width as f64;
+        self.pixel_delta_v = viewport_v / self.image_height as f64;
+
+        let viewport_upper_left =
+            (self.center - (viewport_distance * w)) - (viewport_u / 2.0) - (viewport_v / 2.0);
+        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+
+        let defocus_radius = self.focus_distance * (self.focus_angle / 2.0).to_radians().tan();
+        self.defocus_disk_u = u * defocus_radius;
+        self.defocus_disk_v = v * defocus_radius;
+    }
+
+    pub fn set_view(&mut self, look_from: Point3, look_at: Point3, up_vec: Vec3) {
+        self.center = look_from;
+        self.look_at = look_at;
+        self.up_vec = up_vec;
+        self.update_viewport();
+    }
+
+    // Points the camera at the middle of the world's bounds along the current view direction,
+    // keeping its distance but backing off to at least twice the radius of a sphere around
+    // them, then picks the vfov that just fits that sphere in both directions. Margin adds
+    // that fraction of the radius as space around it. Orthographic views are widened to fit
+    // instead. The focus distance moves to the middle of the scene, and an empty world leaves
+    // the camera as it was.
+    pub fn frame_scene(&mut self, world: &BvhTree, margin: f64) {
+        let bounds = world.bounds();
+        if bounds.is_empty() {
+            return;
+        }
+
+        let (lower, upper) = (bounds.lower(), bounds.upper());
+        let middle = lower + Vec3::from(upper - lower) / 2.0;
+        let radius = Vec3::from(upper - lower).length() / 2.0 * (1.0 + margin.max(0.0));
+
+        let aspect = self.image_width as f64 / self.image_height as f64;
+        let distance = match self.projection {
+            Projection::Perspective => {
+                let distance = Vec3::from(self.center - self.look_at)
+                    .length()
+                    .max(2.0 * radius);
+                // Half the angle the sphere covers, which narrow images need vertically
+                // stretched so it still fits across.
+                let half_angle = (radius / distance).asin();
+                let half_vertical = half_angle.max(f64::atan(half_angle.tan() / aspect));
+                self.vfov = (2.0 * half_vertical).to_degrees();
+                distance
+            }
+            Projection::Orthographic { .. } => {
+                self.projection = Projection::Orthographic {
+                    width: 2.0 * radius * aspect.max(1.0),
+                };
+                2.0 * radius
+            }
+        };
+
+        let w = unit_vector(Vec3::from(self.center - self.look_at));
+        self.look_at = middle;
+        self.center = middle + distance * w;
+        self.focus_distance = distance;
+        self.update_viewport();
+    }
+
+    pub fn set_vfov(&mut self, vfov: f64) {
+        self.vfov = vfov;
+        self.update_viewport();
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.update_viewport();
+    }
+
+    pub fn set_defocus(&mut self, focus_angle: f64, focus_distance: f64) {
+        self.focus_angle = focus_angle;
+        self.focus_distance = focus_distance;
+        self.update_viewport();
+    }
+
+    pub fn set_aperture(&mut self, aperture: Aperture) {
+        self.aperture = aperture;
     }
 
     pub fn set_samples_per_pixel(&mut self, samples: i32) {
         self.samples_per_pixel = samples;
-        self.sample_scale_factor = 1.0 / samples as f64;
+    }
+
+    // Paths that survive past min_bounces are terminated with a probability tied to how
+    // much they can still contribute.
+    pub fn set_russian_roulette(&mut self, min_bounces: u32) {
+        self.russian_roulette = Some(min_bounces);
+    }
+
+    // Samples from the last render that came out NaN or infinite and were counted as black.
+    pub fn non_finite_samples(&self) -> u64 {
+        self.non_finite_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn last_stats(&self) -> RenderStats {
+        RenderStats {
+            primary_rays: self.primary_rays.load(Ordering::Relaxed),
+            bounces: self.bounces.load(Ordering::Relaxed),
+            non_finite_samples: self.non_finite_samples(),
+            elapsed: *self.render_time.lock().unwrap(),
+        }
+    }
+
+    // Scales down any single sample brighter than max_luminance before it is averaged,
+    // trading a little bias for far fewer fireflies from rare bright paths.
+    pub fn set_sample_clamp(&mut self, max_luminance: f64) {
+        self.sample_clamp = Some(max_luminance);
+    }
+
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    // Only applied to the 8-bit formats, EXR output keeps the full linear range.
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        self.tone_map = tone_map;
+    }
+
+    // Gamma the 8-bit formats are encoded with. 1.0 writes linear values, for pipelines that
+    // apply their own transfer curve. EXR output is always linear.
+    pub fn set_gamma(&mut self, gamma: f64) {
+        self.gamma = gamma;
+    }
+
+    pub fn set_pixel_filter(&mut self, filter: PixelFilter) {
+        self.pixel_filter = filter;
+    }
+
+    pub fn set_shading_mode(&mut self, mode: ShadingMode) {
+        self.shading_mode = mode;
+    }
+
+    pub fn set_stratified(&mut self, stratified: bool) {
+        self.stratified = stratified;
+    }
+
+    // Pixels take at least min samples, then stop as soon as the 95% confidence interval of
+    // their luminance is within tolerance of the mean, or max samples is reached.
+    pub fn set_adaptive(&mut self, min_samples: i32, max_samples: i32, tolerance: f64) {
+        self.adaptive = Some(AdaptiveSampling {
+            min_samples: min_samples.max(2),
+            max_samples: max_samples.max(min_samples),
+            tolerance,
+        });
     }
 
     pub fn set_max_depth(&mut self, depth: u32) {
         self.max_depth = depth;
     }
 
-    pub fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
+    // Paths still bouncing at max_depth normally contribute nothing more. With this set they
+    // pick up the background, environment or sky in the direction they were heading, which
+    // is biased but brightens scenes with a lot of inter-reflection.
+    pub fn set_sky_at_max_depth(&mut self, enabled: bool) {
+        self.sky_at_max_depth = enabled;
+    }
+
+    pub fn set_background(&mut self, background: Colour) {
+        self.background = background;
+    }
+
+    // Rays that miss everything look up the environment instead of the flat background.
+    pub fn set_environment(&mut self, environment: EnvironmentMap) {
+        self.environment = Some(environment);
+    }
+
+    // Replaces the flat background with a gradient from bottom to top along up_dir. An
+    // environment map still takes priority if one is set.
+    pub fn set_sky(&mut self, top: Colour, bottom: Colour, up_dir: Vec3) {
+        self.sky = Some(Sky::new(top, bottom, up_dir));
+    }
+
+    // Side length of the square tiles the image is split into for rendering, tiles on the
+    // right and bottom edges are cropped to fit.
+    pub fn set_tile_size(&mut self, size: u64) {
+        self.tile_size = size.max(1);
+    }
+
+    // Restricts rendering to pixels with x0 <= i < x1 and y0 <= j < y1, clamped to the image.
+    // The output keeps its full size, with every pixel outside the region left black.
+    pub fn set_region(&mut self, x0: u64, y0: u64, x1: u64, y1: u64) {
+        let x1 = x1.min(self.image_width);
+        let y1 = y1.min(self.image_height);
+        let x0 = x0.min(x1);
+        let y0 = y0.min(y1);
+
+        self.region = Some(Tile {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        });
+    }
+
+    pub fn clear_region(&mut self) {
+        self.region = None;
+    }
+
+    // Renders in passes of interval samples per pixel, saving the running per-pixel sums to
+    // path after each one so an interrupted render can be picked up with resume_from.
+    // Adaptive sampling picks its own sample counts per pixel, so it is never checkpointed.
+    pub fn set_checkpoint<P: AsRef<Path>>(&mut self, path: P, interval: i32) {
+        self.checkpoint = Some(Checkpointing {
+            path: path.as_ref().to_path_buf(),
+            interval: interval.max(1),
+        });
+    }
+
+    // Every sample draws from its own RNG seeded from this, its pixel and its index, so the
+    // same seed gives the same image however rayon schedules the work. Without a seed each
+    // render picks a fresh one.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    // Starts the next render from the sums saved in a checkpoint, only taking the samples
+    // still needed to reach samples_per_pixel.
+    pub fn resume_from<P: AsRef<Path>>(&mut self, path: P) {
+        self.resume_path = Some(path.as_ref().to_path_buf());
+    }
+
+    // Replaces the default stderr progress bar, for embedding the renderer in another tool.
+    // Called once per row, when the last tile covering it finishes. Rows finish out of order
+    // across threads, so only the count is meaningful. A checkpointed render goes over every
+    // row once per pass, so its total is the row count times the number of passes.
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(u64, u64) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Box::new(callback));
+    }
+
+    // Both ends are clamped to 0..1, the interval moving objects bound their path over, so no
+    // ray can find a moving object outside its bounding box.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = Some((open.clamp(0.0, 1.0), close.clamp(0.0, 1.0)));
+    }
+
+    pub fn shutter(&self) -> Option<(f64, f64)> {
+        self.shutter
+    }
+
+    pub fn defocus_disk_sample<R: Rng>(&self, rng: &mut R) -> Point3 {
+        let p = self.aperture.sample(rng);
         self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
     }
 
-    pub fn render(&mut self, world: &BvhTree) -> io::Result<()> {
-        write!(
-            self.out_file,
-            "P3\n{} {}\n255\n",
-            self.image_width, self.image_height
-        )?;
-
-        let mp = MultiProgress::new();
-
-        let bar_j = mp.add(ProgressBar::new(self.image_height));
-
-        (0..self.image_height).for_each(|j| {
-            bar_j.inc(1);
-            let bar_i = mp.add(ProgressBar::new(self.image_width));
-            let pixel_colours: Vec<_> = (0..self.image_width)
-                .into_par_iter()
-                .map(|i| {
-                    bar_i.inc(1);
-                    let mut avg_colour = Colour::new(0.0, 0.0, 0.0);
-                    (0..self.samples_per_pixel).for_each(|_| {
-                        let r = self.make_ray(i, j);
-                        avg_colour += self.ray_colour(&r, self.max_depth, &world);
-                    });
-                    avg_colour
-                })
+    // The output file is only created once rendering starts, so a bad path doesn't stop a
+    // camera from being built.
+    pub fn render(&self, scene: &Scene) -> io::Result<()> {
+        let file = File::create(&self.out_path)?;
+        self.render_to(scene, BufWriter::new(file))
+    }
+
+    // Writes the image to out in the camera's output format rather than to its output path.
+    pub fn render_to<W: Write>(&self, scene: &Scene, mut out: W) -> io::Result<()> {
+        let image = self.render_to_framebuffer(scene)?;
+        let image = match self.output_format {
+            OutputFormat::Exr => image,
+            _ => image.map(|pixel| self.tone_map.apply(pixel)),
+        };
+
+        self.write_image(&mut out, &image)?;
+        out.flush()
+    }
+
+    // Renders into memory without writing a file. Pixels are the linear averages of their
+    // samples, before any tone mapping.
+    pub fn render_to_framebuffer(&self, scene: &Scene) -> io::Result<Framebuffer> {
+        let pixel_count = (self.image_width * self.image_height) as usize;
+        let start = Instant::now();
+        self.non_finite_samples.store(0, Ordering::Relaxed);
+        self.primary_rays.store(0, Ordering::Relaxed);
+        self.bounces.store(0, Ordering::Relaxed);
+        let (mut sums, mut taken) = match (&self.resume_path, self.adaptive) {
+            (Some(_), Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "adaptive renders can't be resumed from a checkpoint",
+                ));
+            }
+            (Some(path), None) => read_checkpoint(path, self.image_width, self.image_height)?,
+            (None, _) => (vec![Colour::new(0.0, 0.0, 0.0); pixel_count], 0),
+        };
+
+        // Adaptive pixels are averaged as they are rendered, so they take a single pass that
+        // counts as one sample.
+        let checkpoint = self.checkpoint.as_ref().filter(|_| self.adaptive.is_none());
+        let target = if self.adaptive.is_some() {
+            1
+        } else {
+            self.samples_per_pixel
+        };
+        let mut passes = Vec::new();
+        while taken < target {
+            let end = match checkpoint {
+                Some(checkpoint) => (taken + checkpoint.interval).min(target),
+                None => target,
+            };
+            passes.push(taken..end);
+            taken = end;
+        }
+
+        // Tiles come in bands of rows, a band's rows are done once all of its tiles are.
+        let tiles = self.tiles();
+        let first_row = tiles.first().map_or(0, |tile| tile.y);
+        let band_of = |tile: &Tile| ((tile.y - first_row) / self.tile_size) as usize;
+        let mut tiles_per_band = vec![0; tiles.last().map_or(0, |tile| band_of(tile) + 1)];
+        for tile in &tiles {
+            tiles_per_band[band_of(tile)] += 1;
+        }
+
+        let rows = self
+            .region
+            .map_or(self.image_height, |region| region.height);
+        let total_rows = rows * passes.len() as u64;
+        let completed_rows = AtomicU64::new(0);
+        let bar = match self.on_progress {
+            Some(_) => None,
+            None => Some(ProgressBar::new(total_rows)),
+        };
+
+        let seed = self.seed.unwrap_or_else(|| rand::rng().random());
+        for samples in passes {
+            let unfinished: Vec<AtomicU64> = tiles_per_band
+                .iter()
+                .map(|&count| AtomicU64::new(count))
                 .collect();
-            for pix in pixel_colours {
-                self.out_file
-                    .write_fmt(format_args!("{}", pix * self.sample_scale_factor))
-                    .unwrap();
+            self.render_pass(scene, seed, &tiles, &samples, &mut sums, |tile| {
+                if unfinished[band_of(tile)].fetch_sub(1, Ordering::Relaxed) != 1 {
+                    return;
+                }
+                for _ in 0..tile.height {
+                    let completed = completed_rows.fetch_add(1, Ordering::Relaxed) + 1;
+                    match (&self.on_progress, &bar) {
+                        (Some(callback), _) => callback(completed, total_rows),
+                        (None, Some(bar)) => bar.inc(1),
+                        (None, None) => {}
+                    }
+                }
+            });
+
+            if let Some(checkpoint) = checkpoint {
+                write_checkpoint(
+                    &checkpoint.path,
+                    self.image_width,
+                    self.image_height,
+                    samples.end,
+                    &sums,
+                )?;
             }
-            bar_i.finish();
-            mp.remove(&bar_i);
-        });
+        }
 
-        bar_j.finish();
-        self.out_file.flush()
+        if let Some(bar) = bar {
+            bar.finish();
+        }
+
+        *self.render_time.lock().unwrap() = start.elapsed();
+
+        let scale = 1.0 / taken.max(1) as f64;
+        Ok(Framebuffer {
+            width: self.image_width,
+            height: self.image_height,
+            pixels: sums.iter().map(|sum| *sum * scale).collect(),
+        })
     }
 
-    fn ray_colour(&self, ray: &Ray, depth: u32, world: &BvhTree) -> Colour {
-        if depth <= 0 {
-            return Colour::new(0.0, 0.0, 0.0);
+    // Adds the given samples of every pixel in tiles to sums, or with adaptive sampling
+    // replaces them with the pixel's mean, calling tile_done as each tile finishes.
+    fn render_pass<F>(
+        &self,
+        scene: &Scene,
+        seed: u64,
+        tiles: &[Tile],
+        samples: &Range<i32>,
+        sums: &mut [Colour],
+        tile_done: F,
+    ) where
+        F: Fn(&Tile) + Send + Sync,
+    {
+        // Tiles are rendered in parallel into their own buffers, then copied into the row-major
+        // framebuffer. Samples are added straight onto each pixel's running sum, so splitting
+        // a render into passes doesn't change the order of the additions.
+        let rendered: Vec<(Tile, Vec<Colour>)> = tiles
+            .par_iter()
+            .map(|tile| {
+                let mut buffer = Vec::with_capacity((tile.width * tile.height) as usize);
+                let mut counts = RayCounts::default();
+                for j in tile.y..tile.y + tile.height {
+                    for i in tile.x..tile.x + tile.width {
+                        let colour = match self.adaptive {
+                            Some(adaptive) => {
+                                self.render_adaptive_pixel(scene, seed, i, j, adaptive, &mut counts)
+                            }
+                            None => {
+                                let mut sum = sums[(j * self.image_width + i) as usize];
+                                for sample in samples.clone() {
+                                    sum += self.take_sample(scene, seed, i, j, sample, &mut counts);
+                                }
+                                sum
+                            }
+                        };
+                        buffer.push(colour);
+                    }
+                }
+
+                self.primary_rays
+                    .fetch_add(counts.primary_rays, Ordering::Relaxed);
+                self.bounces.fetch_add(counts.bounces, Ordering::Relaxed);
+                tile_done(tile);
+                (*tile, buffer)
+            })
+            .collect();
+
+        for (tile, buffer) in rendered {
+            for (row, tile_row) in buffer.chunks(tile.width as usize).enumerate() {
+                let start = ((tile.y + row as u64) * self.image_width + tile.x) as usize;
+                sums[start..start + tile_row.len()].copy_from_slice(tile_row);
+            }
         }
+    }
+
+    // Renders the beauty pass as usual, then writes the first-hit world-space normal and hit
+    // distance for each pixel centre as float EXR images, for denoisers and compositing. Depth
+    // is repeated across all three channels. Misses get a zero normal and infinite depth.
+    pub fn render_aovs<P, Q>(&self, scene: &Scene, normal_path: P, depth_path: Q) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        self.render(scene)?;
 
-        if let Some(record) = world.hit(ray, 0.001, f64::INFINITY) {
-            let emitted = record
-                .material_ref()
-                .emit(record.u, record.v, &record.hit_pos())
-                .unwrap_or(Colour::new(0.0, 0.0, 0.0));
+        let pixel_count = self.image_width * self.image_height;
+        let first_hits: Vec<(Vec3, f64)> = (0..pixel_count)
+            .into_par_iter()
+            .map(|index| {
+                let i = index % self.image_width;
+                let j = index / self.image_width;
 
-            if let Some(scatter) = record.material_ref().scatter(ray, &record) {
-                let scatter_pdf =
-                    record
-                        .material_ref()
-                        .scatter_pdf(ray, &record, scatter.scattered_ref());
-                let pdf_val = scatter_pdf;
+                let pixel_center = self.pixel00_loc
+                    + (i as f64 * self.pixel_delta_u)
+                    + (j as f64 * self.pixel_delta_v);
+                let time = self.shutter.map_or(0.0, |(open, _)| open);
+                let ray = self.primary_ray(self.center, pixel_center, time);
 
-                let scatter_colour =
-                    (Colour::from(self.ray_colour(scatter.scattered_ref(), depth - 1, world))
-                        * scatter.attenuation()
-                        * scatter_pdf)
-                        / pdf_val;
+                match scene.world.hit(&ray, 0.001, f64::INFINITY) {
+                    Some(record) => (record.normal(), record.t * ray.direction().length()),
+                    None => (Vec3::new(0.0, 0.0, 0.0), f64::INFINITY),
+                }
+            })
+            .collect();
 
-                return scatter_colour + emitted;
-            } else {
-                return emitted;
+        let normals: Vec<Colour> = first_hits
+            .iter()
+            .map(|(normal, _)| Colour::new(normal.x(), normal.y(), normal.z()))
+            .collect();
+        let depths: Vec<Colour> = first_hits
+            .iter()
+            .map(|(_, depth)| Colour::new(*depth, *depth, *depth))
+            .collect();
+
+        let (width, height) = (self.image_width as u32, self.image_height as u32);
+        fs::write(normal_path, encode_exr(width, height, &normals)?)?;
+        fs::write(depth_path, encode_exr(width, height, &depths)?)
+    }
+
+    // Covers the region, or the whole image without one, with tiles starting at its top left.
+    fn tiles(&self) -> Vec<Tile> {
+        let area = self.region.unwrap_or(Tile {
+            x: 0,
+            y: 0,
+            width: self.image_width,
+            height: self.image_height,
+        });
+        let (x_end, y_end) = (area.x + area.width, area.y + area.height);
+
+        let mut tiles = Vec::new();
+        for y in (area.y..y_end).step_by(self.tile_size as usize) {
+            for x in (area.x..x_end).step_by(self.tile_size as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: self.tile_size.min(x_end - x),
+                    height: self.tile_size.min(y_end - y),
+                });
             }
         }
+        tiles
+    }
+
+    fn render_adaptive_pixel(
+        &self,
+        scene: &Scene,
+        seed: u64,
+        i: u64,
+        j: u64,
+        adaptive: AdaptiveSampling,
+        counts: &mut RayCounts,
+    ) -> Colour {
+        let mut sum = Colour::new(0.0, 0.0, 0.0);
+        let mut mean = 0.0;
+        let mut squared_diffs = 0.0;
+        let mut taken = 0;
+
+        // Welford's running mean/variance over the luminance of each sample.
+        while taken < adaptive.max_samples {
+            let sample = self.take_sample(scene, seed, i, j, taken, counts);
+            sum += sample;
+            taken += 1;
+
+            let luminance = sample.luminance();
+            let delta = luminance - mean;
+            mean += delta / taken as f64;
+            squared_diffs += delta * (luminance - mean);
 
-        self.background
+            if taken >= adaptive.min_samples {
+                let variance = squared_diffs / (taken - 1) as f64;
+                let error = 1.96 * f64::sqrt(variance / taken as f64);
+                if error <= adaptive.tolerance * mean.max(1e-4) {
+                    break;
+                }
+            }
+        }
 
-        // let direction = unit_vector(ray.direction());
-        // let scale = 0.5 * (direction.y() + 1.0);
-        // (1.0 - scale) * Colour::new(1.0, 1.0, 1.0) + scale * Colour::new(0.5, 0.7, 1.0)
+        sum / taken as f64
     }
 
-    fn sample_square(&self) -> Vec3 {
-        let mut guard = self.rng_src.lock().expect("Poisoned");
+    // Sample indices stay below 2^31, so pixel and index pack into one key without overlap.
+    fn take_sample(
+        &self,
+        scene: &Scene,
+        seed: u64,
+        i: u64,
+        j: u64,
+        sample: i32,
+        counts: &mut RayCounts,
+    ) -> Colour {
+        let pixel = j * self.image_width + i;
+        let mut rng = SmallRng::seed_from_u64(mix_seed(
+            seed ^ mix_seed((pixel << 32) | sample as u32 as u64),
+        ));
+        let r = self.make_ray(&mut rng, i, j, sample);
+        let colour = self.ray_colour(&mut rng, &r, scene, &mut counts.bounces);
 
-        Vec3::new(
-            guard.random::<f64>() - 0.5,
-            guard.random::<f64>() - 0.5,
-            0.0,
-        )
+        counts.primary_rays += 1;
+        self.clamp_sample(self.finite_sample(colour))
     }
 
-    fn make_ray(&self, i: u64, j: u64) -> Ray {
-        let offset = self.sample_square();
+    // A NaN or infinity from a degenerate path would spread to the whole pixel average, so
+    // such samples are dropped to black and counted instead.
+    fn finite_sample(&self, sample: Colour) -> Colour {
+        if sample.is_finite() {
+            return sample;
+        }
 
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+        self.non_finite_samples.fetch_add(1, Ordering::Relaxed);
+        Colour::new(0.0, 0.0, 0.0)
+    }
 
-        let ray_origin = if self.focus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample()
-        };
-        let ray_direction = Vec3::from(pixel_sample - ray_origin);
-        let ray_time = self
-            .rng_src
-            .lock()
-            .expect("Poisoned RNG source")
-            .random::<f64>();
-        Ray::new(ray_origin, ray_direction, ray_time)
+    fn clamp_sample(&self, sample: Colour) -> Colour {
+        match self.sample_clamp {
+            Some(max_luminance) if sample.luminance() > max_luminance => {
+                sample * (max_luminance / sample.luminance())
+            }
+            _ => sample,
+        }
+    }
+
+    fn write_image<W: Write>(&self, out: &mut W, image: &Framebuffer) -> io::Result<()> {
+        let (width, height) = (image.width as u32, image.height as u32);
+        let pixel_colours = &image.pixels;
+        let bytes = || -> Vec<u8> {
+            pixel_colours
+                .iter()
+                .flat_map(|pix| pix.to_bytes(self.gamma))
+                .collect()
+        };
+
+        let result = match self.output_format {
+            OutputFormat::Ppm => {
+                write!(out, "P3\n{} {}\n255\n", width, height)?;
+                for pix in pixel_colours {
+                    let [r, g, b] = pix.to_bytes(self.gamma);
+                    writeln!(out, "{} {} {}", r, g, b)?;
+                }
+                Ok(())
+            }
+            OutputFormat::Png => {
+                PngEncoder::new(out).write_image(&bytes(), width, height, ExtendedColorType::Rgb8)
+            }
+            OutputFormat::Jpeg => {
+                JpegEncoder::new(out).write_image(&bytes(), width, height, ExtendedColorType::Rgb8)
+            }
+            OutputFormat::Exr => out
+                .write_all(&encode_exr(width, height, pixel_colours)?)
+                .map_err(image::ImageError::IoError),
+        };
+        result.map_err(io::Error::other)
+    }
+
+    // Follows a path iteratively rather than recursing per bounce, carrying the product of
+    // the attenuations seen so far so each bounce's emission can be weighted by it. With
+    // lights set, diffuse bounces add a direct light sample and the emission found by the
+    // next bounce is down-weighted to match, so neither strategy is counted twice.
+    fn ray_colour<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: &Ray,
+        scene: &Scene,
+        bounces: &mut u64,
+    ) -> Colour {
+        if self.shading_mode != ShadingMode::Beauty {
+            return self.debug_colour(ray, scene);
+        }
+
+        let mut colour = Colour::new(0.0, 0.0, 0.0);
+        let mut throughput = Colour::new(1.0, 1.0, 1.0);
+        let mut current = *ray;
+        // Density of the material sampling that produced current, if it was a diffuse
+        // bounce that also sampled the lights.
+        let mut scatter_pdf: Option<f64> = None;
+
+        for bounce in 0..self.max_depth {
+            let emission_weight = match scatter_pdf {
+                Some(pdf) => {
+                    let light_pdf = scene
+                        .lights
+                        .pdf_value(&current.origin(), &current.direction());
+                    power_heuristic(pdf, light_pdf)
+                }
+                None => 1.0,
+            };
+
+            let Some(record) = scene.world.hit(&current, 0.001, f64::INFINITY) else {
+                return colour + throughput * self.miss_colour(&current) * emission_weight;
+            };
+
+            let material = record.material_ref();
+            colour += throughput * material.emitted(&current, &record) * emission_weight;
+
+            match material.scatter(&current, &record, rng) {
+                Some(scatter) => {
+                    let pdf = material.scatter_pdf(&current, &record, scatter.scattered_ref());
+                    scatter_pdf = (pdf > 0.0 && !scene.lights.is_empty()).then_some(pdf);
+
+                    if scatter_pdf.is_some() {
+                        colour += throughput
+                            * scatter.attenuation()
+                            * self.sample_lights(rng, &current, &record, scene);
+                    }
+
+                    throughput = throughput * scatter.attenuation();
+                    current = scatter.scattered();
+                    *bounces += 1;
+                }
+                None => return colour,
+            }
+
+            if let Some(min_bounces) = self.russian_roulette
+                && bounce >= min_bounces
+            {
+                // Survivors are scaled up by 1/p to keep the estimate unbiased.
+                let survival = throughput.max_component().clamp(0.05, 1.0);
+                if rng.random::<f64>() > survival {
+                    return colour;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        if self.sky_at_max_depth {
+            colour += throughput * self.miss_colour(&current);
+        }
+        colour
+    }
+
+    // One light sample from the hit point, before the material's attenuation is applied. The
+    // scatter density doubles as the cosine weighted BRDF divided by the albedo.
+    fn sample_lights<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: &Ray,
+        record: &HitRecord,
+        scene: &Scene,
+    ) -> Colour {
+        let origin = record.hit_pos();
+        let direction = scene.lights.random(&origin, rng);
+        let light_pdf = scene.lights.pdf_value(&origin, &direction);
+        if light_pdf <= 0.0 {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+
+        let material = record.material_ref();
+        let shadow_ray = Ray::new(origin, direction, ray.time());
+        let scatter_pdf = material.scatter_pdf(ray, record, &shadow_ray);
+        if scatter_pdf <= 0.0 {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+
+        let incoming = match scene.world.hit(&shadow_ray, 0.001, f64::INFINITY) {
+            Some(light) => light.material_ref().emitted(&shadow_ray, &light),
+            None => self.miss_colour(&shadow_ray),
+        };
+
+        incoming * (scatter_pdf / light_pdf) * power_heuristic(light_pdf, scatter_pdf)
+    }
+
+    // First hit shading for the modes other than Beauty.
+    fn debug_colour(&self, ray: &Ray, scene: &Scene) -> Colour {
+        let Some(record) = scene.world.hit(ray, 0.001, f64::INFINITY) else {
+            return Colour::new(0.0, 0.0, 0.0);
+        };
+        let material = record.material_ref();
+
+        match self.shading_mode {
+            ShadingMode::Normals => {
+                let normal = material.shading_normal(&record);
+                Colour::new(normal.x() + 1.0, normal.y() + 1.0, normal.z() + 1.0) * 0.5
+            }
+            ShadingMode::Albedo => material.albedo(&record),
+            ShadingMode::Depth => {
+                // Distance to the furthest corner of the bounds, no hit can be further away.
+                let bounds = scene.world.bounds();
+                let far = (0..8)
+                    .map(|corner| {
+                        let pick = |axis: usize| {
+                            if corner & (1 << axis) == 0 {
+                                bounds.lower().axis(axis)
+                            } else {
+                                bounds.upper().axis(axis)
+                            }
+                        };
+                        Vec3::from(Point3::new(pick(0), pick(1), pick(2)) - ray.origin()).length()
+                    })
+                    .fold(0.0, f64::max);
+
+                let distance = record.t * ray.direction().length();
+                let depth = (1.0 - distance / far).clamp(0.0, 1.0);
+                Colour::new(depth, depth, depth)
+            }
+            ShadingMode::Beauty => unreachable!("beauty shading traces paths in ray_colour"),
+        }
+    }
+
+    fn miss_colour(&self, ray: &Ray) -> Colour {
+        match (&self.environment, &self.sky) {
+            (Some(environment), _) => environment.value(&ray.direction()),
+            (None, Some(sky)) => sky.value(&ray.direction()),
+            (None, None) => self.background,
+        }
+    }
+
+    // In stratified mode the pixel is split into a sqrt(spp) x sqrt(spp) grid and each of the
+    // first grid-size samples is jittered within its own cell, any left over are uniform. The
+    // point in the square is then shaped by the pixel filter.
+    fn sample_offset<R: Rng>(&self, rng: &mut R, sample: i32) -> Vec3 {
+        let grid = f64::sqrt(self.samples_per_pixel as f64) as i32;
+        let (x, y) = if !self.stratified || sample >= grid * grid {
+            (rng.random::<f64>(), rng.random::<f64>())
+        } else {
+            let cell_x = (sample % grid) as f64;
+            let cell_y = (sample / grid) as f64;
+            let recip_grid = 1.0 / grid as f64;
+
+            (
+                (cell_x + rng.random::<f64>()) * recip_grid,
+                (cell_y + rng.random::<f64>()) * recip_grid,
+            )
+        };
+
+        let (offset_x, offset_y) = self.pixel_filter.warp(x, y);
+        Vec3::new(offset_x, offset_y, 0.0)
+    }
+
+    fn make_ray<R: Rng>(&self, rng: &mut R, i: u64, j: u64, sample: i32) -> Ray {
+        let offset = self.sample_offset(rng, sample);
+
+        let pixel_sample = self.pixel00_loc
+            + ((i as f64 + offset.x()) * self.pixel_delta_u)
+            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+
+        let ray_origin = if self.focus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(rng)
+        };
+        let ray_time = match self.shutter {
+            Some((open, close)) if close > open => open + rng.random::<f64>() * (close - open),
+            Some((open, _)) => open,
+            None => 0.0,
+        };
+        self.primary_ray(ray_origin, pixel_sample, ray_time)
+    }
+
+    // Orthographic rays ignore the lens and travel straight out from the pixel.
+    fn primary_ray(&self, origin: Point3, pixel_sample: Point3, time: f64) -> Ray {
+        match self.projection {
+            Projection::Perspective => Ray::new(origin, Vec3::from(pixel_sample - origin), time),
+            Projection::Orthographic { .. } => Ray::new(pixel_sample, -self.w, time),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CameraError {
+    Io(io::Error),
+    // Vertical field of view in degrees, which has to be strictly between 0 and 180.
+    InvalidFov(f64),
+    ZeroDimension { width: u64, aspect_ratio: f64 },
+    // Looking from the point being looked at, or with up parallel to the view direction,
+    // leaves no way to orient the camera.
+    DegenerateView,
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::Io(err) => write!(f, "{}", err),
+            CameraError::InvalidFov(vfov) => write!(
+                f,
+                "vertical field of view must be between 0 and 180 degrees, got {}",
+                vfov
+            ),
+            CameraError::ZeroDimension {
+                width,
+                aspect_ratio,
+            } => write!(
+                f,
+                "camera needs a positive width and aspect ratio, got {} and {}",
+                width, aspect_ratio
+            ),
+            CameraError::DegenerateView => write!(
+                f,
+                "camera has no view direction, look_from equals look_at or up is along the view"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CameraError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CameraError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CameraError {
+    fn from(err: io::Error) -> Self {
+        CameraError::Io(err)
+    }
+}
+
+impl From<CameraError> for io::Error {
+    fn from(err: CameraError) -> Self {
+        match err {
+            CameraError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidInput, err.to_string()),
+        }
+    }
+}
+
+// Named alternative to Camera::new's positional arguments. Anything left unset falls back to
+// a 400 pixel wide 16:9 image looking down -z from the origin with a 90 degree field of view,
+// written to image.ppm.
+#[derive(Debug, Clone)]
+pub struct CameraBuilder {
+    aspect_ratio: f64,
+    image_width: u64,
+    image_height: Option<u64>,
+    vfov: f64,
+    look_from: Point3,
+    look_at: Point3,
+    up: Vec3,
+    focus_distance: f64,
+    focus_angle: f64,
+    output: PathBuf,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<u32>,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        CameraBuilder {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            image_height: None,
+            vfov: 90.0,
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            focus_distance: 10.0,
+            focus_angle: 0.0,
+            output: PathBuf::from("image.ppm"),
+            samples_per_pixel: None,
+            max_depth: None,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn new() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn image_width(mut self, image_width: u64) -> Self {
+        self.image_width = image_width;
+        self
+    }
+
+    // Sets both dimensions exactly, taking the place of the aspect ratio.
+    pub fn dimensions(mut self, image_width: u64, image_height: u64) -> Self {
+        self.image_width = image_width;
+        self.image_height = Some(image_height);
+        self
+    }
+
+    pub fn vfov(mut self, vfov: f64) -> Self {
+        self.vfov = vfov;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: Point3) -> Self {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Point3) -> Self {
+        self.look_at = look_at;
+        self
+    }
+
+    pub fn up(mut self, up: Vec3) -> Self {
+        self.up = up;
+        self
+    }
+
+    pub fn focus_distance(mut self, focus_distance: f64) -> Self {
+        self.focus_distance = focus_distance;
+        self
+    }
+
+    pub fn focus_angle(mut self, focus_angle: f64) -> Self {
+        self.focus_angle = focus_angle;
+        self
+    }
+
+    pub fn output<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.output = path.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples: i32) -> Self {
+        self.samples_per_pixel = Some(samples);
+        self
+    }
+
+    pub fn max_depth(mut self, depth: u32) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    pub fn build(&self) -> Result<Camera, CameraError> {
+        let mut camera = match self.image_height {
+            Some(image_height) => Camera::with_dimensions(
+                self.image_width,
+                image_height,
+                self.vfov,
+                self.look_from,
+                self.look_at,
+                self.up,
+                self.focus_distance,
+                self.focus_angle,
+                &self.output,
+            )?,
+            None => Camera::new(
+                self.aspect_ratio,
+                self.image_width,
+                self.vfov,
+                self.look_from,
+                self.look_at,
+                self.up,
+                self.focus_distance,
+                self.focus_angle,
+                &self.output,
+            )?,
+        };
+        if let Some(samples) = self.samples_per_pixel {
+            camera.set_samples_per_pixel(samples);
+        }
+        if let Some(depth) = self.max_depth {
+            camera.set_max_depth(depth);
+        }
+
+        Ok(camera)
+    }
+}
+
+// SplitMix64's finaliser, spreading nearby inputs like consecutive pixel indices across the
+// whole seed space.
+fn mix_seed(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Weight for a sample drawn with density pdf when another strategy could have produced the
+// same direction with density other_pdf.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf_squared = pdf * pdf;
+    pdf_squared / (pdf_squared + other_pdf * other_pdf)
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"TRCKPT01";
+
+// Little endian: magic, width and height as u64, samples taken as i32, then the running
+// RGB sums for each pixel in row-major order as f64. Written to a temporary file and renamed
+// over the old checkpoint, so a crash mid-write leaves the previous one intact.
+fn write_checkpoint(
+    path: &Path,
+    width: u64,
+    height: u64,
+    samples: i32,
+    sums: &[Colour],
+) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let mut out = BufWriter::new(File::create(&temp_path)?);
+    out.write_all(CHECKPOINT_MAGIC)?;
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    out.write_all(&samples.to_le_bytes())?;
+    for sum in sums {
+        for channel in [sum.r(), sum.g(), sum.b()] {
+            out.write_all(&channel.to_le_bytes())?;
+        }
+    }
+    out.flush()?;
+    drop(out);
+
+    fs::rename(&temp_path, path)
+}
+
+fn read_checkpoint(path: &Path, width: u64, height: u64) -> io::Result<(Vec<Colour>, i32)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let invalid = |message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("checkpoint {}: {}", path.display(), message),
+        )
+    };
+
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != CHECKPOINT_MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+
+    let mut word = [0; 8];
+    input.read_exact(&mut word)?;
+    let saved_width = u64::from_le_bytes(word);
+    input.read_exact(&mut word)?;
+    let saved_height = u64::from_le_bytes(word);
+    if (saved_width, saved_height) != (width, height) {
+        return Err(invalid(&format!(
+            "saved at {}x{} but the camera is {}x{}",
+            saved_width, saved_height, width, height
+        )));
+    }
+
+    let mut count = [0; 4];
+    input.read_exact(&mut count)?;
+    let samples = i32::from_le_bytes(count);
+
+    let mut channel = || -> io::Result<f64> {
+        input.read_exact(&mut word)?;
+        Ok(f64::from_le_bytes(word))
+    };
+    let sums = (0..width * height)
+        .map(|_| Ok(Colour::new(channel()?, channel()?, channel()?)))
+        .collect::<io::Result<Vec<Colour>>>()?;
+
+    Ok((sums, samples))
+}
+
+// Linear 32-bit float RGB. The EXR encoder needs to seek, so the file is built in memory.
+fn encode_exr(width: u32, height: u32, pixels: &[Colour]) -> io::Result<Vec<u8>> {
+    let floats: Vec<u8> = pixels
+        .iter()
+        .flat_map(|pix| [pix.r() as f32, pix.g() as f32, pix.b() as f32])
+        .flat_map(|channel| channel.to_ne_bytes())
+        .collect();
+    let mut buffer = Cursor::new(Vec::new());
+    OpenExrEncoder::new(&mut buffer)
+        .write_image(&floats, width, height, ExtendedColorType::Rgb32F)
+        .map_err(io::Error::other)?;
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::testing::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn metal_and_matte_spheres_render_differently() {
+        let render = |material: Arc<dyn Material>| {
+            let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, -2.0), 0.8, material)]);
+            let mut camera = test_camera(16, 16, 16);
+            camera.set_sky(
+                Colour::new(0.2, 0.4, 1.0),
+                Colour::new(1.0, 0.9, 0.8),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+            camera.render_to_framebuffer(&scene).unwrap()
+        };
+
+        let metal = render(Metalic::as_arc(Colour::new(0.9, 0.9, 0.9), 0.0));
+        let matte = render(grey());
+
+        let difference: f64 = (6..10)
+            .map(|x| (metal.pixel(x, 6).luminance() - matte.pixel(x, 6).luminance()).abs())
+            .sum();
+        assert!(
+            difference > 0.1,
+            "top centre pixels barely differ: {difference}"
+        );
+    }
+
+    #[test]
+    fn parallel_render_matches_serial_render() {
+        let scene = scene_of(vec![
+            sphere(
+                Point3::new(-0.5, 0.0, -2.0),
+                0.5,
+                Lambertian::as_arc_from_colour(Colour::new(0.8, 0.3, 0.3)),
+            ),
+            sphere(
+                Point3::new(0.5, 0.0, -2.5),
+                0.5,
+                Metalic::as_arc(Colour::new(0.8, 0.8, 0.8), 0.2),
+            ),
+        ]);
+        let mut camera = test_camera(24, 16, 8);
+        camera.set_background(Colour::new(0.7, 0.8, 1.0));
+        camera.set_tile_size(8);
+
+        let render_with = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| camera.render_to_framebuffer(&scene).unwrap())
+        };
+
+        assert_eq!(channels(&render_with(1)), channels(&render_with(4)));
+    }
+
+    #[test]
+    fn aovs_hold_smooth_normals_and_the_nearest_depth_at_the_centre() {
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, -3.0), 1.0, grey())]);
+        let beauty_path = temp_path("beauty.ppm");
+        let mut camera = Camera::builder()
+            .dimensions(15, 15)
+            .look_from(Point3::new(0.0, 0.0, 0.0))
+            .look_at(Point3::new(0.0, 0.0, -1.0))
+            .vfov(45.0)
+            .samples_per_pixel(1)
+            .output(&beauty_path)
+            .build()
+            .unwrap();
+        camera.set_progress_callback(|_, _| {});
+        let (normal_path, depth_path) = (temp_path("normals.exr"), temp_path("depth.exr"));
+        camera
+            .render_aovs(&scene, &normal_path, &depth_path)
+            .unwrap();
+        fs::remove_file(&beauty_path).unwrap();
+
+        let normals = image::open(&normal_path).unwrap().into_rgb32f();
+        let depths = image::open(&depth_path).unwrap().into_rgb32f();
+        fs::remove_file(&normal_path).unwrap();
+        fs::remove_file(&depth_path).unwrap();
+
+        // Facing the camera at the centre, leaning further out towards the silhouette.
+        assert!(normals.get_pixel(7, 7)[2] > 0.99);
+        let row: Vec<f32> = (3..12).map(|x| normals.get_pixel(x, 7)[0]).collect();
+        assert!(
+            row.windows(2)
+                .all(|pair| pair[0] < pair[1] && pair[1] - pair[0] < 0.4)
+        );
+
+        let centre = depths.get_pixel(7, 7)[0];
+        assert!((centre - 2.0).abs() < 1e-3);
+        assert!(depths.pixels().all(|pixel| pixel[0] >= centre));
+        assert!(depths.get_pixel(0, 0)[0].is_infinite());
+    }
+
+    fn matte_ball_scene() -> Scene {
+        scene_of(vec![sphere(
+            Point3::new(0.0, 0.0, -2.0),
+            0.8,
+            Lambertian::as_arc_from_colour(Colour::new(0.6, 0.4, 0.3)),
+        )])
+    }
+
+    #[test]
+    fn progress_is_reported_once_per_row() {
+        let scene = matte_ball_scene();
+        let mut camera = test_camera(20, 13, 1);
+        camera.set_tile_size(4);
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        camera
+            .set_progress_callback(move |done, total| recorded.lock().unwrap().push((done, total)));
+        camera.render_to_framebuffer(&scene).unwrap();
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 13);
+        assert!(calls.iter().all(|&(_, total)| total == 13));
+        assert_eq!(calls.last(), Some(&(13, 13)));
+    }
+
+    #[test]
+    fn tile_size_does_not_change_the_image() {
+        let scene = matte_ball_scene();
+        let render_in_tiles = |size: u64| {
+            let mut camera = test_camera(70, 40, 4);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_tile_size(size);
+            camera.render_to_framebuffer(&scene).unwrap()
+        };
+
+        assert_eq!(
+            channels(&render_in_tiles(16)),
+            channels(&render_in_tiles(64))
+        );
+    }
+
+    #[test]
+    fn resuming_from_a_checkpoint_matches_an_uninterrupted_render() {
+        let scene = matte_ball_scene();
+        let checkpoint = temp_path("resume.checkpoint");
+        let camera_with = |samples: i32| {
+            let mut camera = test_camera(12, 10, samples);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera
+        };
+
+        let mut interrupted = camera_with(3);
+        interrupted.set_checkpoint(&checkpoint, 3);
+        interrupted.render_to_framebuffer(&scene).unwrap();
+        let mut resumed = camera_with(8);
+        resumed.resume_from(&checkpoint);
+        let resumed = resumed.render_to_framebuffer(&scene).unwrap();
+        fs::remove_file(&checkpoint).unwrap();
+
+        let uninterrupted = camera_with(8).render_to_framebuffer(&scene).unwrap();
+        assert_eq!(channels(&resumed), channels(&uninterrupted));
+    }
+
+    #[test]
+    fn exr_output_skips_the_tone_map() {
+        let scene = matte_ball_scene();
+        let mut camera = test_camera(8, 8, 2);
+        camera.set_background(Colour::new(4.0, 3.0, 2.0));
+        camera.set_output_format(OutputFormat::Exr);
+        camera.set_tone_map(ToneMap::Reinhard);
+
+        let mut exr = Vec::new();
+        camera.render_to(&scene, &mut exr).unwrap();
+        let written = image::load_from_memory(&exr).unwrap().into_rgb32f();
+        let linear = camera.render_to_framebuffer(&scene).unwrap();
+
+        assert!(written.get_pixel(0, 0)[0] > 1.0);
+        for (pixel, expected) in written.pixels().zip(channels(&linear)) {
+            assert_eq!(pixel[0], expected[0] as f32);
+        }
+    }
+
+    #[test]
+    fn the_same_seed_writes_the_same_bytes() {
+        let scene = matte_ball_scene();
+        let render = |seed: u64| {
+            let mut camera = test_camera(12, 12, 4);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_output_format(OutputFormat::Png);
+            camera.set_seed(seed);
+            let mut bytes = Vec::new();
+            camera.render_to(&scene, &mut bytes).unwrap();
+            bytes
+        };
+
+        assert_eq!(render(61), render(61));
+        assert_ne!(render(61), render(62));
+    }
+
+    #[test]
+    fn a_pixel_looking_at_a_light_sees_its_emission() {
+        let emission = Colour::new(4.0, 2.0, 1.0);
+        let light = crate::quad::Quad::boxed(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            DiffuseLight::as_arc_from_colour(emission),
+        );
+        let scene = scene_of(vec![light]);
+        let image = test_camera(9, 9, 4).render_to_framebuffer(&scene).unwrap();
+
+        let centre = image.pixel(4, 4);
+        for (seen, emitted) in [centre.r(), centre.g(), centre.b()].into_iter().zip([
+            emission.r(),
+            emission.g(),
+            emission.b(),
+        ]) {
+            assert!((seen - emitted).abs() < 1e-9, "{seen} != {emitted}");
+        }
+    }
+
+    #[test]
+    fn framing_a_scene_brings_every_bounding_box_corner_into_view() {
+        let grey = grey();
+        let mut world = BvhTree::new();
+        world.add(sphere(Point3::new(10.0, 2.0, -30.0), 1.0, grey.clone()));
+        world.add(Box::new(crate::quad::Cube::new(
+            Point3::new(4.0, -3.0, -24.0),
+            Point3::new(7.0, 5.0, -20.0),
+            grey,
+        )));
+        world.build();
+        let (lower, upper) = (world.bounds().lower(), world.bounds().upper());
+
+        for (width, height) in [(40, 20), (20, 40)] {
+            let mut camera = test_camera(width, height, 1);
+            camera.frame_scene(&world, 0.0);
+
+            for corner in 0..8 {
+                let pick = |axis: usize| match corner >> axis & 1 {
+                    0 => lower.axis(axis),
+                    _ => upper.axis(axis),
+                };
+                let corner = Point3::new(pick(0), pick(1), pick(2));
+
+                // Where the ray from the eye through the corner crosses the viewport, in pixels.
+                let normal = cross(camera.pixel_delta_u, camera.pixel_delta_v);
+                let to_corner = Vec3::from(corner - camera.center);
+                let to_plane = Vec3::from(camera.pixel00_loc - camera.center);
+                let on_plane =
+                    camera.center + to_corner * (dot(to_plane, normal) / dot(to_corner, normal));
+                let offset = Vec3::from(on_plane - camera.pixel00_loc);
+                let x = dot(offset, camera.pixel_delta_u) / camera.pixel_delta_u.length_squared();
+                let y = dot(offset, camera.pixel_delta_v) / camera.pixel_delta_v.length_squared();
+
+                assert!(dot(to_corner, camera.w) < 0.0, "corner behind the camera");
+                assert!(x >= -0.5 && x <= width as f64 - 0.5, "x {x} out of {width}");
+                assert!(
+                    y >= -0.5 && y <= height as f64 - 0.5,
+                    "y {y} out of {height}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_samples_are_dropped_and_counted() {
+        let broken = DiffuseLight::as_arc_from_colour(Colour::new(f64::NAN, f64::INFINITY, 1.0));
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, -2.0), 0.5, broken)]);
+        let mut camera = test_camera(10, 10, 4);
+        camera.set_background(Colour::new(0.7, 0.8, 1.0));
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+
+        assert!(camera.non_finite_samples() > 0);
+        assert!(channels(&image).iter().flatten().all(|c| c.is_finite()));
+        assert_eq!(channels(&image)[0], [0.7, 0.8, 1.0]);
+        assert_eq!(channels(&image)[5 * 10 + 5], [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn a_zero_defocus_angle_keeps_every_ray_on_the_pinhole() {
+        let mut camera = test_camera(8, 8, 1);
+        camera.set_defocus(0.0, 3.0);
+        let mut rng = SmallRng::seed_from_u64(3);
+        for sample in 0..32 {
+            assert_eq!(
+                camera.make_ray(&mut rng, 4, 4, sample).origin(),
+                camera.center
+            );
+        }
+
+        // Ten degrees at a focus distance of 3 gives a lens of radius 3 tan(5 degrees).
+        camera.set_defocus(10.0, 3.0);
+        let lens_radius = 3.0 * 5.0_f64.to_radians().tan();
+        let offsets: Vec<f64> = (0..32)
+            .map(|sample| {
+                let origin = camera.make_ray(&mut rng, 4, 4, sample).origin();
+                Vec3::from(origin - camera.center).length()
+            })
+            .collect();
+        assert!(offsets.iter().all(|&offset| offset <= lens_radius + 1e-9));
+        assert!(offsets.iter().any(|&offset| offset > 0.1 * lens_radius));
+    }
+
+    #[test]
+    fn a_camera_looking_along_x_puts_a_sphere_on_x_in_the_middle() {
+        let red = DiffuseLight::as_arc_from_colour(Colour::new(1.0, 0.0, 0.0));
+        let scene = scene_of(vec![sphere(Point3::new(3.0, 0.0, 0.0), 0.5, red)]);
+        let mut camera = test_camera(9, 9, 1);
+        camera.set_view(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+
+        assert_eq!(channels(&image)[4 * 9 + 4], [1.0, 0.0, 0.0]);
+        for corner in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+            assert_eq!(image.pixel(corner.0, corner.1).luminance(), 0.0);
+        }
+    }
+
+    #[test]
+    fn ray_times_fall_inside_the_shutter_interval() {
+        let mut camera = test_camera(8, 8, 1);
+        let mut rng = SmallRng::seed_from_u64(8);
+        for sample in 0..16 {
+            assert_eq!(camera.make_ray(&mut rng, 2, 3, sample).time(), 0.0);
+        }
+
+        camera.set_shutter(0.25, 0.75);
+        let times: Vec<f64> = (0..256)
+            .map(|sample| camera.make_ray(&mut rng, 2, 3, sample).time())
+            .collect();
+        assert!(times.iter().all(|time| (0.25..=0.75).contains(time)));
+        assert!(times.iter().any(|&time| time < 0.3) && times.iter().any(|&time| time > 0.7));
+    }
+
+    #[test]
+    fn the_shutter_is_clamped_to_the_interval_moving_objects_are_bounded_over() {
+        let mut camera = test_camera(8, 8, 1);
+        camera.set_shutter(-0.5, 2.0);
+        assert_eq!(camera.shutter(), Some((0.0, 1.0)));
+
+        let mut rng = SmallRng::seed_from_u64(10);
+        for sample in 0..64 {
+            assert!((0.0..=1.0).contains(&camera.make_ray(&mut rng, 0, 0, sample).time()));
+        }
+    }
+
+    #[test]
+    fn rendering_to_a_png_path_writes_a_decodable_png_matching_the_ppm() {
+        let scene = matte_ball_scene();
+        let render_as = |name: &str| {
+            let path = temp_path(name);
+            let mut camera = Camera::builder()
+                .dimensions(12, 7)
+                .samples_per_pixel(2)
+                .output(&path)
+                .build()
+                .unwrap();
+            camera.set_seed(14);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_progress_callback(|_, _| {});
+            camera.render(&scene).unwrap();
+            let image = image::open(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            image
+        };
+
+        let png = render_as("out.png");
+        assert_eq!((png.width(), png.height()), (12, 7));
+        assert_eq!(png.into_rgb8(), render_as("out.ppm").into_rgb8());
+    }
+
+    #[test]
+    fn a_black_background_leaves_only_the_light_visible() {
+        let light = crate::quad::Quad::boxed(
+            Point3::new(-0.5, -0.5, -2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            DiffuseLight::as_arc_from_colour(Colour::new(5.0, 5.0, 5.0)),
+        );
+        let scene = scene_of(vec![light]);
+        let mut camera = test_camera(16, 16, 8);
+        camera.set_background(Colour::new(0.0, 0.0, 0.0));
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+
+        // The light covers the middle quarter of the view, pixels 6 to 9 on each axis.
+        for (x, y) in (0..16).flat_map(|y| (0..16).map(move |x| (x, y))) {
+            let luminance = image.pixel(x, y).luminance();
+            if (6..10).contains(&x) && (6..10).contains(&y) {
+                assert!(luminance > 4.0, "pixel {x},{y} misses the light");
+            } else if !(5..11).contains(&x) || !(5..11).contains(&y) {
+                assert!(luminance < 1e-9, "pixel {x},{y} is lit");
+            }
+        }
+    }
+
+    #[test]
+    fn a_deep_chain_of_glass_spheres_renders_on_a_small_stack() {
+        let glass: Vec<Box<dyn Hittable>> = (0..60)
+            .map(|k| {
+                sphere(
+                    Point3::new(0.0, 0.0, -2.0 - k as f64),
+                    0.45,
+                    Dielectric::as_arc(1.5, Colour::new(1.0, 1.0, 1.0)),
+                ) as Box<dyn Hittable>
+            })
+            .collect();
+        let scene = scene_of(glass);
+        let mut camera = test_camera(4, 4, 4);
+        camera.set_max_depth(1000);
+        camera.set_background(Colour::new(0.7, 0.8, 1.0));
+
+        // A recursive integrator a thousand bounces deep would not fit in 128 KiB of stack.
+        let image = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .stack_size(128 * 1024)
+            .build()
+            .unwrap()
+            .install(|| camera.render_to_framebuffer(&scene).unwrap());
+
+        assert!(image.pixels.iter().all(|p| p.luminance().is_finite()));
+        assert!(image.pixel(2, 2).luminance() > 0.0);
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean_brightness() {
+        let scene = scene_of(vec![
+            sphere(
+                Point3::new(0.0, 0.0, -2.0),
+                0.8,
+                Lambertian::as_arc_from_colour(Colour::new(0.8, 0.8, 0.8)),
+            ),
+            sphere(
+                Point3::new(0.0, -100.8, -2.0),
+                100.0,
+                Lambertian::as_arc_from_colour(Colour::new(0.7, 0.7, 0.7)),
+            ),
+        ]);
+        let mean_brightness = |roulette: bool| {
+            let mut camera = test_camera(16, 16, 128);
+            camera.set_max_depth(50);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            if roulette {
+                camera.set_russian_roulette(2);
+            }
+            let image = camera.render_to_framebuffer(&scene).unwrap();
+            image.pixels.iter().map(|p| p.luminance()).sum::<f64>() / image.pixels.len() as f64
+        };
+
+        let (plain, roulette) = (mean_brightness(false), mean_brightness(true));
+        assert!(
+            (plain - roulette).abs() < 0.02 * plain,
+            "{plain} without roulette, {roulette} with"
+        );
+    }
+
+    #[test]
+    fn reinhard_brings_a_bright_pixel_below_white_where_none_clamps_it() {
+        let bright = Colour::new(5.0, 5.0, 5.0);
+        assert!(ToneMap::Reinhard.apply(bright).r() < 1.0);
+        assert_eq!(ToneMap::None.apply(bright), bright);
+
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, 5.0), 0.5, grey())]);
+        let written = |tone_map: ToneMap| {
+            let mut camera = test_camera(2, 2, 1);
+            camera.set_background(bright);
+            camera.set_tone_map(tone_map);
+            let mut out = Vec::new();
+            camera.render_to(&scene, &mut out).unwrap();
+            let text = String::from_utf8(out).unwrap();
+            text.lines()
+                .nth(3)
+                .unwrap()
+                .split(' ')
+                .map(|v| v.parse::<u8>().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(written(ToneMap::None), [255, 255, 255]);
+        assert!(written(ToneMap::Reinhard).iter().all(|&c| c < 255));
+    }
+
+    #[test]
+    fn stratified_sampling_has_lower_variance_over_a_gradient() {
+        // Each pixel estimate averages a quantity that ramps smoothly across the pixel.
+        let variance = |stratified: bool| {
+            let mut camera = test_camera(8, 8, 16);
+            camera.set_stratified(stratified);
+            let mut rng = SmallRng::seed_from_u64(12);
+            let estimates: Vec<f64> = (0..200)
+                .map(|_| {
+                    (0..16)
+                        .map(|sample| {
+                            let direction = camera.make_ray(&mut rng, 3, 4, sample).direction();
+                            direction.x() + 0.5 * direction.y()
+                        })
+                        .sum::<f64>()
+                        / 16.0
+                })
+                .collect();
+            let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+            estimates.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / estimates.len() as f64
+        };
+
+        let (uniform, stratified) = (variance(false), variance(true));
+        assert!(
+            stratified < 0.5 * uniform,
+            "stratified {stratified} against uniform {uniform}"
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_on_a_flat_light_but_not_on_a_noisy_edge() {
+        let light = DiffuseLight::as_arc_from_colour(Colour::new(2.0, 2.0, 2.0));
+        let flat = scene_of(vec![crate::quad::Quad::boxed(
+            Point3::new(-5.0, -5.0, -1.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 10.0, 0.0),
+            light,
+        )]);
+        let edge = matte_ball_scene();
+        let render = |scene: &Scene, adaptive: bool| {
+            let mut camera = test_camera(6, 6, 256);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            if adaptive {
+                camera.set_adaptive(4, 256, 0.02);
+            }
+            let image = camera.render_to_framebuffer(scene).unwrap();
+            (image, camera.last_stats().primary_rays)
+        };
+
+        let (flat_image, flat_rays) = render(&flat, true);
+        assert_eq!(flat_rays, 36 * 4);
+        assert!(
+            flat_image
+                .pixels
+                .iter()
+                .all(|&p| p == Colour::new(2.0, 2.0, 2.0))
+        );
+
+        let (edge_image, edge_rays) = render(&edge, true);
+        assert!(edge_rays > 4 * flat_rays, "{edge_rays} rays on the edge");
+        let (reference, _) = render(&edge, false);
+        let mean = |image: &Framebuffer| {
+            image.pixels.iter().map(|p| p.luminance()).sum::<f64>() / image.pixels.len() as f64
+        };
+        assert!((mean(&edge_image) - mean(&reference)).abs() < 0.05 * mean(&reference));
+    }
+
+    #[test]
+    fn sampling_a_small_light_converges_faster_in_a_cornell_box() {
+        use crate::hittable::HittableList;
+        use crate::quad::Quad;
+
+        let wall = |q: Point3, u: Vec3, v: Vec3| -> Box<dyn Hittable> {
+            Quad::boxed(
+                q,
+                u,
+                v,
+                Lambertian::as_arc_from_colour(Colour::new(0.7, 0.7, 0.7)),
+            )
+        };
+        let light = || {
+            Quad::boxed(
+                Point3::new(-0.2, 0.999, -2.2),
+                Vec3::new(0.4, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.4),
+                DiffuseLight::as_arc_from_colour(Colour::new(15.0, 15.0, 15.0)),
+            )
+        };
+        let (x, y, z) = (
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(0.0, 0.0, -2.0),
+        );
+        let walls = || {
+            vec![
+                wall(Point3::new(-1.0, -1.0, -1.0), x, z),
+                wall(Point3::new(-1.0, 1.0, -1.0), x, z),
+                wall(Point3::new(-1.0, -1.0, -3.0), x, y),
+                wall(Point3::new(-1.0, -1.0, -1.0), y, z),
+                wall(Point3::new(1.0, -1.0, -1.0), y, z),
+                light(),
+            ]
+        };
+        let mut lights = HittableList::new();
+        lights.add(light());
+        let with_nee = Scene::with_lights(BvhTree::from_objects(walls()), lights);
+        let without = scene_of(walls());
+
+        // Two renders that differ only in seed, how far apart they land is the noise.
+        let noise = |scene: &Scene| {
+            let render = |seed: u64| {
+                let mut camera = test_camera(8, 8, 16);
+                camera.set_seed(seed);
+                camera.set_max_depth(8);
+                camera.set_background(Colour::new(0.0, 0.0, 0.0));
+                camera.render_to_framebuffer(scene).unwrap()
+            };
+            let (a, b) = (render(1), render(2));
+            a.pixels
+                .iter()
+                .zip(&b.pixels)
+                .map(|(p, q)| (p.luminance() - q.luminance()).powi(2))
+                .sum::<f64>()
+        };
+
+        let (nee, plain) = (noise(&with_nee), noise(&without));
+        assert!(
+            nee < 0.5 * plain,
+            "{nee} with light sampling, {plain} without"
+        );
+    }
+
+    #[test]
+    fn rendering_into_a_byte_buffer_writes_a_ppm_header() {
+        let mut camera = test_camera(5, 3, 1);
+        camera.set_output_format(OutputFormat::Ppm);
+        let mut out = Vec::new();
+        camera.render_to(&matte_ball_scene(), &mut out).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("P3"));
+        assert_eq!(lines.next(), Some("5 3"));
+        assert_eq!(lines.next(), Some("255"));
+        assert_eq!(lines.count(), 15);
+    }
+
+    #[test]
+    fn a_region_render_leaves_pixels_outside_it_black() {
+        let scene = matte_ball_scene();
+        let mut camera = test_camera(12, 10, 2);
+        camera.set_background(Colour::new(0.7, 0.8, 1.0));
+        let full = camera.render_to_framebuffer(&scene).unwrap();
+        camera.set_region(4, 3, 8, 6);
+        let region = camera.render_to_framebuffer(&scene).unwrap();
+
+        assert_eq!((region.width, region.height), (12, 10));
+        assert_eq!(camera.last_stats().primary_rays, 4 * 3 * 2);
+        for (x, y) in (0..10).flat_map(|y| (0..12).map(move |x| (x, y))) {
+            if (4..8).contains(&x) && (3..6).contains(&y) {
+                assert_eq!(region.pixel(x, y), full.pixel(x, y));
+            } else {
+                assert_eq!(region.pixel(x, y), Colour::new(0.0, 0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn clamping_caps_the_brightest_sample_under_a_tiny_light() {
+        let scene = scene_of(vec![
+            sphere(Point3::new(0.0, 0.0, -2.0), 0.8, grey()),
+            sphere(
+                Point3::new(0.0, 1.2, -1.2),
+                0.05,
+                DiffuseLight::as_arc_from_colour(Colour::new(2000.0, 2000.0, 2000.0)),
+            ),
+        ]);
+        let brightest = |clamp: Option<f64>| {
+            let mut camera = test_camera(8, 8, 1);
+            camera.set_background(Colour::new(0.0, 0.0, 0.0));
+            if let Some(max_luminance) = clamp {
+                camera.set_sample_clamp(max_luminance);
+            }
+            (0..2000)
+                .map(|sample| {
+                    let mut counts = RayCounts::default();
+                    camera
+                        .take_sample(&scene, 7, 4, 2, sample, &mut counts)
+                        .luminance()
+                })
+                .fold(0.0, f64::max)
+        };
+
+        let unclamped = brightest(None);
+        assert!(unclamped > 50.0, "no firefly found, brightest {unclamped}");
+        assert!(brightest(Some(10.0)) <= 10.0 + 1e-9);
+    }
+
+    #[test]
+    fn orthographic_spheres_at_different_depths_look_the_same_size() {
+        let scene = scene_of(vec![
+            sphere(
+                Point3::new(-1.0, 0.0, -3.0),
+                0.5,
+                DiffuseLight::as_arc_from_colour(Colour::new(1.0, 0.0, 0.0)),
+            ),
+            sphere(
+                Point3::new(1.0, 0.0, -9.0),
+                0.5,
+                DiffuseLight::as_arc_from_colour(Colour::new(0.0, 0.0, 1.0)),
+            ),
+        ]);
+        // Pixels showing the near red sphere and the far blue one.
+        let sizes = |projection: Projection| {
+            let mut camera = test_camera(40, 20, 4);
+            camera.set_background(Colour::new(0.0, 0.0, 0.0));
+            camera.set_projection(projection);
+            let image = camera.render_to_framebuffer(&scene).unwrap();
+            let count =
+                |covers: fn(&Colour) -> bool| image.pixels.iter().filter(|p| covers(p)).count();
+            (count(|p| p.r() > 0.5), count(|p| p.b() > 0.5))
+        };
+
+        let (near, far) = sizes(Projection::Orthographic { width: 4.0 });
+        assert!(near > 20, "near sphere covers {near} pixels");
+        assert!(
+            near.abs_diff(far) <= 2,
+            "{near} pixels near against {far} far"
+        );
+
+        let (near, far) = sizes(Projection::Perspective);
+        assert!(near > 2 * far);
+    }
+
+    #[test]
+    fn a_camera_built_from_width_and_aspect_ratio_alone_renders_with_defaults() {
+        let mut camera = Camera::builder()
+            .image_width(12)
+            .aspect_ratio(2.0)
+            .build()
+            .unwrap();
+        assert_eq!((camera.image_width, camera.image_height), (12, 6));
+        assert_eq!(camera.samples_per_pixel, 10);
+        assert_eq!(camera.max_depth, 10);
+
+        camera.set_progress_callback(|_, _| {});
+        let image = camera.render_to_framebuffer(&matte_ball_scene()).unwrap();
+        assert_eq!((image.width, image.height), (12, 6));
+        assert_eq!(camera.last_stats().primary_rays, 12 * 6 * 10);
+    }
+
+    #[test]
+    fn camera_new_rejects_a_zero_fov_and_a_coincident_view() {
+        let at = Point3::new(0.0, 0.0, -1.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let output = temp_path("unused.ppm");
+        let make = |vfov: f64, look_from: Point3| {
+            Camera::new(16.0 / 9.0, 32, vfov, look_from, at, up, 1.0, 0.0, &output)
+        };
+
+        assert!(matches!(
+            make(0.0, Point3::new(0.0, 0.0, 0.0)),
+            Err(CameraError::InvalidFov(fov)) if fov == 0.0
+        ));
+        assert!(matches!(make(90.0, at), Err(CameraError::DegenerateView)));
+        assert!(make(90.0, Point3::new(0.0, 0.0, 0.0)).is_ok());
+
+        assert!(matches!(
+            Camera::builder().vfov(0.0).build(),
+            Err(CameraError::InvalidFov(_))
+        ));
+        assert!(matches!(
+            Camera::builder().look_from(at).look_at(at).build(),
+            Err(CameraError::DegenerateView)
+        ));
+    }
+
+    #[test]
+    fn the_framebuffer_matches_the_camera_and_sees_a_red_sphere_in_the_middle() {
+        let scene = scene_of(vec![sphere(
+            Point3::new(0.0, 0.0, -2.0),
+            0.6,
+            Lambertian::as_arc_from_colour(Colour::new(0.9, 0.1, 0.1)),
+        )]);
+        let mut camera = test_camera(11, 7, 8);
+        camera.set_background(Colour::new(0.8, 0.8, 0.8));
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+
+        assert_eq!((image.width, image.height), (11, 7));
+        assert_eq!(image.pixels.len(), 77);
+        let centre = image.pixel(5, 3);
+        assert!(
+            centre.r() > 2.0 * centre.g() && centre.r() > 2.0 * centre.b(),
+            "centre pixel {centre:?}"
+        );
+    }
+
+    #[test]
+    fn polygon_aperture_samples_stay_inside_the_polygon() {
+        let mut rng = SmallRng::seed_from_u64(89);
+        for sides in [3u32, 5, 6, 8] {
+            let step = 2.0 * f64::consts::PI / sides as f64;
+            let inradius = f64::cos(step / 2.0);
+            let mut reaches_a_corner = false;
+            for _ in 0..2000 {
+                let p = Aperture::Polygon(sides).sample(&mut rng);
+                assert!(p.length() <= 1.0 + 1e-12 && p.z() == 0.0);
+                // Within each edge's half plane, the edge lying inradius from the centre.
+                for edge in 0..sides {
+                    let angle = (edge as f64 + 0.5) * step;
+                    let towards_edge = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                    assert!(dot(p, towards_edge) <= inradius + 1e-12);
+                }
+                reaches_a_corner |= p.length() > inradius;
+            }
+            assert!(
+                reaches_a_corner,
+                "{sides} sides never left the inscribed circle"
+            );
+        }
+
+        for aperture in [Aperture::Circle, Aperture::Polygon(2)] {
+            for _ in 0..500 {
+                assert!(aperture.sample(&mut rng).length() < 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn with_dimensions_keeps_exactly_1920_by_1080() {
+        let mut camera = Camera::with_dimensions(
+            1920,
+            1080,
+            90.0,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            temp_path("unused.ppm"),
+        )
+        .unwrap();
+        assert_eq!((camera.image_width, camera.image_height), (1920, 1080));
+
+        // Only the header is needed, a one pixel region keeps the render itself cheap.
+        camera.set_region(0, 0, 1, 1);
+        camera.set_samples_per_pixel(1);
+        camera.set_progress_callback(|_, _| {});
+        let mut out = Vec::new();
+        camera.render_to(&matte_ball_scene(), &mut out).unwrap();
+        let header: Vec<&[u8]> = out.split(|&b| b == b'\n').take(3).collect();
+        assert_eq!(header, [&b"P3"[..], b"1920 1080", b"255"]);
+    }
+
+    #[test]
+    fn a_gaussian_filter_softens_a_hard_edge_that_box_keeps_sharp() {
+        // A light over the right half of the view, its edge on the boundary of columns 3 and 4.
+        let scene = scene_of(vec![crate::quad::Quad::boxed(
+            Point3::new(0.0, -10.0, -1.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            DiffuseLight::as_arc_from_colour(Colour::new(1.0, 1.0, 1.0)),
+        )]);
+        let row = |filter: PixelFilter| {
+            let mut camera = test_camera(8, 4, 256);
+            camera.set_background(Colour::new(0.0, 0.0, 0.0));
+            camera.set_pixel_filter(filter);
+            let image = camera.render_to_framebuffer(&scene).unwrap();
+            (0..8).map(|i| image.pixel(i, 2).r()).collect::<Vec<_>>()
+        };
+        let steepest = |row: &[f64]| {
+            row.windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .fold(0.0, f64::max)
+        };
+        let blended = |row: &[f64]| row.iter().filter(|&&v| v > 0.02 && v < 0.98).count();
+
+        let sharp = row(PixelFilter::Box);
+        let soft = row(PixelFilter::Gaussian(0.6));
+        assert_eq!(blended(&sharp), 0, "box row {sharp:?}");
+        assert!(blended(&soft) >= 2, "gaussian row {soft:?}");
+        assert!(
+            steepest(&soft) < 0.8 * steepest(&sharp),
+            "gaussian {soft:?} against box {sharp:?}"
+        );
+    }
+
+    #[test]
+    fn sky_at_max_depth_brightens_a_closed_mirror_room() {
+        // Inside a mirror sphere no path ever reaches the background on its own.
+        let scene = scene_of(vec![sphere(
+            Point3::new(0.0, 0.0, 0.0),
+            5.0,
+            Metalic::as_arc(Colour::new(0.9, 0.9, 0.9), 0.0),
+        )]);
+        let mean = |sky_at_max_depth: bool| {
+            let mut camera = test_camera(6, 4, 4);
+            camera.set_max_depth(8);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_sky_at_max_depth(sky_at_max_depth);
+            let image = camera.render_to_framebuffer(&scene).unwrap();
+            image.pixels.iter().map(|p| p.luminance()).sum::<f64>() / image.pixels.len() as f64
+        };
+
+        assert_eq!(mean(false), 0.0);
+        let lit = mean(true);
+        assert!(lit > 0.2, "mean luminance with the sky {lit}");
+    }
+
+    #[test]
+    fn two_lights_are_both_sampled_and_light_the_floor_twice_as_much() {
+        use crate::hittable::HittableList;
+        use crate::quad::Quad;
+
+        let floor = || {
+            Quad::boxed(
+                Point3::new(-3.0, -1.0, -1.0),
+                Vec3::new(6.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -4.0),
+                grey(),
+            )
+        };
+        // Small lamps either side of the view, above the floor.
+        let lamp = |x: f64| {
+            Quad::boxed(
+                Point3::new(x - 0.25, 1.0, -3.25),
+                Vec3::new(0.5, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.5),
+                DiffuseLight::as_arc_from_colour(Colour::new(10.0, 10.0, 10.0)),
+            )
+        };
+        let scene_with = |xs: &[f64]| {
+            let mut objects: Vec<Box<dyn Hittable>> = vec![floor()];
+            let mut lights = HittableList::new();
+            for &x in xs {
+                objects.push(lamp(x));
+                lights.add(lamp(x));
+            }
+            Scene::with_lights(BvhTree::from_objects(objects), lights)
+        };
+        let both = scene_with(&[-1.5, 1.5]);
+
+        // Light samples from the middle of the floor reach each lamp about half the time.
+        let on_floor = Point3::new(0.0, -1.0, -3.0);
+        let mut rng = SmallRng::seed_from_u64(103);
+        let mut reached = [0, 0];
+        for _ in 0..1000 {
+            let direction = both.lights.random(&on_floor, &mut rng);
+            let hit = both
+                .world
+                .hit(&Ray::new(on_floor, direction, 0.0), 0.001, f64::INFINITY)
+                .unwrap();
+            reached[(hit.hit_pos().axis(0) > 0.0) as usize] += 1;
+        }
+        assert!(reached.iter().all(|&n| n > 400), "lamp hits {reached:?}");
+
+        let floor_brightness = |scene: &Scene| {
+            let mut camera = test_camera(8, 8, 16);
+            camera.set_max_depth(4);
+            camera.set_background(Colour::new(0.0, 0.0, 0.0));
+            let image = camera.render_to_framebuffer(scene).unwrap();
+            image.pixels[32..]
+                .iter()
+                .map(|p| p.luminance())
+                .sum::<f64>()
+        };
+        let (two, one) = (
+            floor_brightness(&both),
+            floor_brightness(&scene_with(&[-1.5])),
+        );
+        assert!(
+            (1.6..2.4).contains(&(two / one)),
+            "{two} with both lamps, {one} with one"
+        );
+    }
+
+    #[test]
+    fn a_10_by_10_render_at_4_samples_reports_400_primary_rays() {
+        let scene = matte_ball_scene();
+        let stats = |tile_size: u64| {
+            let mut camera = test_camera(10, 10, 4);
+            camera.set_tile_size(tile_size);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.render_to_framebuffer(&scene).unwrap();
+            camera.last_stats()
+        };
+
+        // Counts are merged per tile, uneven edge tiles included.
+        let (small_tiles, one_tile) = (stats(3), stats(16));
+        assert_eq!(small_tiles.primary_rays, 400);
+        assert_eq!(one_tile.primary_rays, 400);
+        assert!(small_tiles.bounces > 0);
+        assert_eq!(small_tiles.bounces, one_tile.bounces);
+    }
+
+    #[test]
+    fn normals_mode_renders_a_smoothly_shaded_disc() {
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, -2.0), 1.2, grey())]);
+        let mut camera = test_camera(16, 16, 4);
+        camera.set_shading_mode(ShadingMode::Normals);
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+        // The disc reaches 6 pixels out, pixels on its rim are part background.
+        let inside = |i: u64, j: u64| (i as f64 - 8.0).powi(2) + (j as f64 - 8.0).powi(2) < 20.0;
+
+        // Facing the camera in the middle, the normal's x growing from left to right.
+        let centre = image.pixel(8, 8);
+        assert!(
+            centre.b() > 0.95 && (centre.r() - 0.5).abs() < 0.05,
+            "{centre:?}"
+        );
+        assert!((4..12).all(|i| image.pixel(i + 1, 8).r() > image.pixel(i, 8).r()));
+
+        // Neighbours inside the disc never jump, and the corners miss it.
+        for j in 0..16 {
+            for i in 0..15 {
+                if inside(i, j) && inside(i + 1, j) {
+                    let (a, b) = (image.pixel(i, j), image.pixel(i + 1, j));
+                    assert!((a.r() - b.r()).abs() < 0.15, "jump at ({i}, {j})");
+                    assert!((a.g() - b.g()).abs() < 0.15, "jump at ({i}, {j})");
+                }
+            }
+        }
+        assert_eq!(image.pixel(0, 0), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn albedo_mode_shows_base_colours_and_depth_mode_fades_with_distance() {
+        let scene = scene_of(vec![
+            sphere(
+                Point3::new(-1.5, 0.0, -2.0),
+                0.5,
+                Lambertian::as_arc_from_colour(Colour::new(0.9, 0.1, 0.1)),
+            ),
+            sphere(
+                Point3::new(1.5, 0.0, -6.0),
+                1.5,
+                Lambertian::as_arc_from_colour(Colour::new(0.1, 0.2, 0.8)),
+            ),
+        ]);
+        let render = |mode: ShadingMode| {
+            let mut camera = test_camera(20, 10, 1);
+            camera.set_background(Colour::new(0.7, 0.8, 1.0));
+            camera.set_shading_mode(mode);
+            camera.render_to_framebuffer(&scene).unwrap()
+        };
+        // Pixels over the middle of the near and the far sphere, and one over neither.
+        let (near, far, empty) = ((6, 5), (11, 5), (10, 0));
+
+        let albedo = render(ShadingMode::Albedo);
+        assert_eq!(albedo.pixel(near.0, near.1), Colour::new(0.9, 0.1, 0.1));
+        assert_eq!(albedo.pixel(far.0, far.1), Colour::new(0.1, 0.2, 0.8));
+        assert_eq!(albedo.pixel(empty.0, empty.1), Colour::new(0.0, 0.0, 0.0));
+
+        let depth = render(ShadingMode::Depth);
+        let (near, far) = (depth.pixel(near.0, near.1), depth.pixel(far.0, far.1));
+        assert!(near.r() > far.r() && far.r() > 0.0 && near.r() < 1.0);
+        assert!(near.r() == near.g() && near.g() == near.b());
+        assert_eq!(depth.pixel(empty.0, empty.1), Colour::new(0.0, 0.0, 0.0));
     }
 }
@@ -0,0 +1,211 @@
+use crate::bounding::*;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::vec3::*;
+
+use core::f64;
+use std::sync::Arc;
+
+pub struct Cylinder {
+    base: Point3,
+    axis: Vec3,
+    radius: f64,
+    height: f64,
+    mat: Arc<dyn Material>,
+    capped: bool,
+    bounds: BoundingBox,
+
+    // Perpendicular to axis and each other, used to measure the angle around the cylinder.
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        mat: Arc<dyn Material>,
+        capped: bool,
+    ) -> Cylinder {
+        let axis = unit_vector(axis);
+        let top = base + (height * axis);
+
+        // A disc of radius r around a unit axis reaches r * sqrt(1 - a_i^2) along world axis i.
+        let extent = Vec3::new(
+            radius * f64::sqrt(1.0 - axis.x() * axis.x()),
+            radius * f64::sqrt(1.0 - axis.y() * axis.y()),
+            radius * f64::sqrt(1.0 - axis.z() * axis.z()),
+        );
+        let base_box = BoundingBox::new(base - extent, base + extent);
+        let top_box = BoundingBox::new(top - extent, top + extent);
+
+        let (bitangent, tangent) = orthonormal_basis(axis);
+
+        Cylinder {
+            base,
+            axis,
+            radius,
+            height,
+            mat,
+            capped,
+            bounds: BoundingBox::box_between(&base_box, &top_box),
+            tangent,
+            bitangent,
+        }
+    }
+
+    pub fn boxed(
+        base: Point3,
+        axis: Vec3,
+        radius: f64,
+        height: f64,
+        mat: Arc<dyn Material>,
+        capped: bool,
+    ) -> Box<Cylinder> {
+        Box::new(Cylinder::new(base, axis, radius, height, mat, capped))
+    }
+
+    // Nearest t in the open interval where the ray meets the curved side between the caps.
+    fn hit_side(&self, oc: Vec3, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<f64> {
+        let d_along = dot(r.direction(), self.axis);
+        let oc_along = dot(oc, self.axis);
+
+        let d_perp = r.direction() - (d_along * self.axis);
+        let oc_perp = oc - (oc_along * self.axis);
+
+        let a = d_perp.length_squared();
+        if a < 1e-12 {
+            return None;
+        }
+        let h = dot(d_perp, oc_perp);
+        let c = oc_perp.length_squared() - self.radius * self.radius;
+
+        let discriminant = (h * h) - (a * c);
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = f64::sqrt(discriminant);
+        [(-h - sqrtd) / a, (-h + sqrtd) / a].into_iter().find(|&t| {
+            let along = oc_along + t * d_along;
+            ray_tmin < t && t < ray_tmax && (0.0..=self.height).contains(&along)
+        })
+    }
+
+    // Nearest t where the ray crosses either end disc, along with which one it was.
+    fn hit_caps(&self, oc: Vec3, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<(f64, bool)> {
+        let d_along = dot(r.direction(), self.axis);
+        if f64::abs(d_along) < 1e-8 {
+            return None;
+        }
+        let oc_along = dot(oc, self.axis);
+
+        [(0.0, false), (self.height, true)]
+            .into_iter()
+            .filter_map(|(plane, top)| {
+                let t = (plane - oc_along) / d_along;
+                if t <= ray_tmin || ray_tmax <= t {
+                    return None;
+                }
+
+                let offset = oc + (t * r.direction()) - (plane * self.axis);
+                (offset.length_squared() <= self.radius * self.radius).then_some((t, top))
+            })
+            .min_by(|(t1, _), (t2, _)| t1.total_cmp(t2))
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let oc = Vec3::from(r.origin() - self.base);
+
+        let side = self.hit_side(oc, r, ray_tmin, ray_tmax);
+        let cap = if self.capped {
+            self.hit_caps(oc, r, ray_tmin, side.unwrap_or(ray_tmax))
+        } else {
+            None
+        };
+
+        let (t, outward_normal, u, v) = match (side, cap) {
+            (_, Some((t, top))) => {
+                let p = r.at(t);
+                let offset = Vec3::from(p - self.base);
+                let u = 0.5 + dot(offset, self.tangent) / (2.0 * self.radius);
+                let v = 0.5 + dot(offset, self.bitangent) / (2.0 * self.radius);
+                let normal = if top { self.axis } else { -self.axis };
+                (t, normal, u, v)
+            }
+            (Some(t), None) => {
+                let offset = Vec3::from(r.at(t) - self.base);
+                let along = dot(offset, self.axis);
+                let radial = (offset - (along * self.axis)) / self.radius;
+
+                let phi = f64::atan2(dot(radial, self.bitangent), dot(radial, self.tangent))
+                    + f64::consts::PI;
+                (
+                    t,
+                    radial,
+                    phi / (2.0 * f64::consts::PI),
+                    along / self.height,
+                )
+            }
+            (None, None) => return None,
+        };
+
+        let mut record = HitRecord::with_uv(r.at(t), outward_normal, t, self.mat.clone(), u, v);
+        record.set_face_normal(r, outward_normal);
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> &BoundingBox {
+        &self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    // Radius 1, standing from y = -1 to y = 1, three units down -z.
+    fn pillar(capped: bool) -> Cylinder {
+        Cylinder::new(
+            Point3::new(0.0, -1.0, -3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            grey(),
+            capped,
+        )
+    }
+
+    #[test]
+    fn a_ray_at_the_side_hits_the_curved_surface() {
+        let towards = ray(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = pillar(false).hit(&towards, 0.001, f64::INFINITY).unwrap();
+
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.normal().approx_eq(&Vec3::new(0.0, 0.0, 1.0), 1e-9));
+        assert!(hit.front_face());
+    }
+
+    #[test]
+    fn a_capped_cylinder_is_hit_on_its_top_cap() {
+        let down = ray(Point3::new(0.2, 3.0, -3.1), Vec3::new(0.0, -1.0, 0.0));
+        let hit = pillar(true).hit(&down, 0.001, f64::INFINITY).unwrap();
+
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.normal().approx_eq(&Vec3::new(0.0, 1.0, 0.0), 1e-9));
+        assert!(hit.front_face());
+    }
+
+    #[test]
+    fn an_uncapped_cylinder_lets_a_ray_through_its_ends() {
+        let down = ray(Point3::new(0.2, 3.0, -3.1), Vec3::new(0.0, -1.0, 0.0));
+        assert!(pillar(false).hit(&down, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::texture::*;
+use crate::vec3::*;
+
+use core::f64;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Lights rays that leave the scene with an equirectangular image, u running around the
+// horizon and v from straight down to straight up.
+pub struct EnvironmentMap {
+    texture: Arc<dyn Texture>,
+}
+
+impl EnvironmentMap {
+    pub fn new(texture: Arc<dyn Texture>) -> EnvironmentMap {
+        EnvironmentMap { texture }
+    }
+
+    pub fn from_hdr<P>(path: P) -> io::Result<EnvironmentMap>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(EnvironmentMap::new(Arc::new(HdrTexture::open(path)?)))
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Colour {
+        let d = unit_vector(*direction);
+
+        let u = 0.5 + f64::atan2(d.z(), d.x()) / (2.0 * f64::consts::PI);
+        let v = 0.5 + f64::asin(d.y().clamp(-1.0, 1.0)) / f64::consts::PI;
+
+        self.texture.value(u, v, Point3::from(d))
+    }
+}
+
+// Two colour sky blending from bottom, looking straight down, to top, looking straight along
+// up. Up doesn't have to be +y, tilting it tilts the horizon.
+pub struct Sky {
+    top: Colour,
+    bottom: Colour,
+    up: Vec3,
+}
+
+impl Sky {
+    pub fn new(top: Colour, bottom: Colour, up: Vec3) -> Sky {
+        Sky {
+            top,
+            bottom,
+            up: unit_vector(up),
+        }
+    }
+
+    pub fn value(&self, direction: &Vec3) -> Colour {
+        let t = 0.5 * (dot(unit_vector(*direction), self.up) + 1.0);
+
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn a_solid_environment_returns_its_colour_for_every_miss() {
+        let teal = Colour::new(0.1, 0.6, 0.5);
+        let environment = EnvironmentMap::new(Arc::new(SolidColour::new(teal)));
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-0.3, 0.2, -5.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        ] {
+            assert_eq!(environment.value(&direction), teal);
+        }
+
+        // Nothing in front of the camera, so every sample is a miss.
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, 5.0), 0.5, grey())]);
+        let mut camera = test_camera(6, 4, 2);
+        camera.set_environment(environment);
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+        assert!(
+            image
+                .pixels
+                .iter()
+                .all(|&pixel| pixel.approx_eq(&teal, 1e-12))
+        );
+    }
+
+    #[test]
+    fn a_sky_with_up_along_x_varies_along_x_instead_of_y() {
+        let (top, bottom) = (Colour::new(0.2, 0.4, 1.0), Colour::new(1.0, 0.9, 0.8));
+        let sky = Sky::new(top, bottom, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(sky.value(&Vec3::new(1.0, 0.0, 0.0)), top);
+        assert_eq!(sky.value(&Vec3::new(-1.0, 0.0, 0.0)), bottom);
+        assert_eq!(
+            sky.value(&Vec3::new(0.0, 1.0, 0.0)),
+            sky.value(&Vec3::new(0.0, -1.0, 0.0))
+        );
+
+        let scene = scene_of(vec![sphere(Point3::new(0.0, 0.0, 5.0), 0.5, grey())]);
+        let mut camera = test_camera(9, 9, 4);
+        camera.set_sky(top, bottom, Vec3::new(1.0, 0.0, 0.0));
+        let image = camera.render_to_framebuffer(&scene).unwrap();
+        let blue = |x: u64, y: u64| image.pixel(x, y).b();
+
+        let across = blue(8, 4) - blue(0, 4);
+        let down = (blue(4, 0) - blue(4, 8)).abs();
+        assert!(across > 0.1, "left to right changes by {across}");
+        assert!(down < 0.1 * across, "top to bottom changes by {down}");
+    }
+}
@@ -0,0 +1,33 @@
+use crate::vec3::*;
+
+// Rendered pixels held in memory, in row-major order from the top left.
+pub struct Framebuffer {
+    pub width: u64,
+    pub height: u64,
+    pub pixels: Vec<Colour>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u64, height: u64) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Colour::new(0.0, 0.0, 0.0); (width * height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u64, y: u64) -> Colour {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn map<F>(&self, fun: F) -> Framebuffer
+    where
+        F: Fn(Colour) -> Colour,
+    {
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|pixel| fun(*pixel)).collect(),
+        }
+    }
+}
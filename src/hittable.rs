@@ -1,14 +1,17 @@
 use crate::bounding::*;
-use crate::material;
 use crate::material::Material;
 use crate::ray::*;
 use crate::vec3::*;
 use core::f64;
+use rand::{Rng, RngCore};
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct HitRecord {
     p: Point3,
     normal: Vec3,
+    tangent: Option<Vec3>,
+    bitangent: Option<Vec3>,
     pub t: f64,
     front_face: bool,
     material: Arc<dyn Material>,
@@ -17,7 +20,12 @@ pub struct HitRecord {
 }
 
 impl HitRecord {
-    pub fn new(
+    // For shapes without a UV parameterisation, u and v are left at zero.
+    pub fn new(p: Point3, normal: Vec3, t: f64, material: Arc<dyn Material>) -> HitRecord {
+        HitRecord::with_uv(p, normal, t, material, 0.0, 0.0)
+    }
+
+    pub fn with_uv(
         p: Point3,
         normal: Vec3,
         t: f64,
@@ -28,6 +36,8 @@ impl HitRecord {
         HitRecord {
             p,
             normal,
+            tangent: None,
+            bitangent: None,
             t,
             front_face: false,
             material,
@@ -63,6 +73,37 @@ impl HitRecord {
         self.normal
     }
 
+    pub fn tangent(&self) -> Option<Vec3> {
+        self.tangent
+    }
+
+    // Directions of increasing u and increasing v, for shapes that have a UV parameterisation.
+    pub fn set_tangent_frame(&mut self, tangent: Vec3, bitangent: Vec3) {
+        self.tangent = Some(tangent);
+        self.bitangent = Some(bitangent);
+    }
+
+    pub fn bitangent(&self) -> Option<Vec3> {
+        self.bitangent
+    }
+
+    // Unit tangent and bitangent perpendicular to the shading normal. Built from whatever the
+    // shape provided, or an arbitrary frame around the normal when it provided nothing.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        match self.tangent {
+            Some(tangent) => {
+                let tangent = unit_vector(tangent - dot(tangent, self.normal) * self.normal);
+                let bitangent = cross(self.normal, tangent);
+                match self.bitangent {
+                    // Keep the handedness the shape's v direction implies.
+                    Some(b) if dot(b, bitangent) < 0.0 => (tangent, -bitangent),
+                    _ => (tangent, bitangent),
+                }
+            }
+            None => orthonormal_basis(self.normal),
+        }
+    }
+
     pub fn hit_pos(&self) -> Point3 {
         self.p
     }
@@ -81,11 +122,53 @@ impl HittableList {
         }
     }
 
+    // Objects with an empty box, like an empty nested list, leave the bounds alone.
     pub fn add(&mut self, object: Box<dyn Hittable>) {
-        let bounds = BoundingBox::box_between(&self.bounds, object.bounding_box());
-        self.bounds = bounds;
+        if !object.bounding_box().is_empty() {
+            self.bounds = BoundingBox::box_between(&self.bounds, object.bounding_box());
+        }
         self.hittables.push(object);
     }
+
+    // Recomputes the bounds from scratch, for when contained objects may have changed. An
+    // empty list gets BoundingBox::empty().
+    pub fn rebuild_bounds(&mut self) {
+        self.bounds = self
+            .hittables
+            .iter()
+            .map(|object| object.bounding_box())
+            .filter(|bounds| !bounds.is_empty())
+            .fold(BoundingBox::empty(), |bounds, object_bounds| {
+                BoundingBox::box_between(&bounds, object_bounds)
+            });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hittables.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hittables.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.hittables.clear();
+        self.bounds = BoundingBox::empty();
+    }
+
+    // Panics if index is out of range, like Vec::remove. The bounds are rebuilt from what's
+    // left, since the removed object may have been the one stretching them.
+    pub fn remove(&mut self, index: usize) -> Box<dyn Hittable> {
+        let object = self.hittables.remove(index);
+        self.rebuild_bounds();
+        object
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
 }
 
 impl Hittable for HittableList {
@@ -105,12 +188,45 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> &BoundingBox {
         &self.bounds
     }
+
+    // Each object is equally likely to be picked, so the density is the average of theirs.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.hittables.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.hittables.len() as f64;
+        self.hittables
+            .iter()
+            .map(|x| weight * x.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut dyn RngCore) -> Vec3 {
+        if self.hittables.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        let index = rng.random_range(0..self.hittables.len());
+        self.hittables[index].random(origin, rng)
+    }
 }
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord>;
 
     fn bounding_box(&self) -> &BoundingBox;
+
+    // Solid angle density, as seen from origin, of random() picking the given direction.
+    // Shapes that can't be sampled as lights keep the default of zero.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // A direction from origin towards a random point on the shape.
+    fn random(&self, _origin: &Point3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub struct Translate {
@@ -145,11 +261,20 @@ impl Hittable for Translate {
 
         if let Some(mut hit) = self.object.hit(&offset_r, ray_tmin, ray_tmax) {
             hit.p += self.offset;
-            return Some(hit);
+            Some(hit)
         } else {
             None
         }
     }
+
+    // Directions are unchanged by a translation, only the origin moves into object space.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&(*origin - self.offset), direction)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut dyn RngCore) -> Vec3 {
+        self.object.random(&(*origin - self.offset), rng)
+    }
 }
 
 pub struct RotateY {
@@ -180,6 +305,22 @@ impl RotateY {
     pub fn boxed(object: Box<dyn Hittable>, angle: f64) -> Box<RotateY> {
         Box::new(RotateY::new(object, angle))
     }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            (self.cos_theta * v.x()) - (self.sin_theta * v.z()),
+            v.y(),
+            (self.sin_theta * v.x()) + (self.cos_theta * v.z()),
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            (self.cos_theta * v.x()) + (self.sin_theta * v.z()),
+            v.y(),
+            (-self.sin_theta * v.x()) + (self.cos_theta * v.z()),
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -188,36 +329,182 @@ impl Hittable for RotateY {
     }
 
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
-        let origin = Point3::new(
-            (self.cos_theta * r.origin().axis(0)) - (self.sin_theta * r.origin().axis(2)),
-            r.origin().axis(1),
-            (self.sin_theta * r.origin().axis(0)) + (self.cos_theta * r.origin().axis(2)),
-        );
-
-        let direction = Vec3::new(
-            (self.cos_theta * r.direction().x()) - (self.sin_theta * r.direction().z()),
-            r.direction().y(),
-            (self.sin_theta * r.direction().x()) + (self.cos_theta * r.direction().z()),
-        );
-
+        let origin = Point3::from(self.to_object(Vec3::from(r.origin())));
+        let direction = self.to_object(r.direction());
         let rotated_r = Ray::new(origin, direction, r.time());
 
         if let Some(mut hit) = self.object.hit(&rotated_r, ray_tmin, ray_tmax) {
-            hit.p = Point3::new(
-                (self.cos_theta * hit.p.axis(0)) + (self.sin_theta * hit.p.axis(2)),
-                hit.p.axis(1),
-                (-self.sin_theta * hit.p.axis(0)) + (self.cos_theta * hit.p.axis(2)),
-            );
-
-            hit.normal = Vec3::new(
-                (self.cos_theta * hit.normal.axis(0)) + (self.sin_theta * hit.normal.axis(2)),
-                hit.normal.axis(1),
-                (-self.sin_theta * hit.normal.axis(0)) + (self.cos_theta * hit.normal.axis(2)),
-            );
-
-            return Some(hit);
+            hit.p = Point3::from(self.to_world(Vec3::from(hit.p)));
+            hit.normal = self.to_world(hit.normal);
+            hit.tangent = hit.tangent.map(|tangent| self.to_world(tangent));
+            hit.bitangent = hit.bitangent.map(|bitangent| self.to_world(bitangent));
+
+            Some(hit)
         } else {
             None
         }
     }
+
+    // The density of a direction is the object's density for that direction rotated into
+    // object space, and sampled directions are rotated back out.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let origin = Point3::from(self.to_object(Vec3::from(*origin)));
+        self.object.pdf_value(&origin, &self.to_object(*direction))
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let origin = Point3::from(self.to_object(Vec3::from(*origin)));
+        self.to_world(self.object.random(&origin, rng))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quad::Quad;
+    use crate::testing::*;
+
+    fn from_origin(direction: Vec3) -> Ray {
+        ray(Point3::new(0.0, 0.0, 0.0), direction)
+    }
+
+    #[test]
+    fn translate_moves_hits_by_the_offset() {
+        let sphere = sphere(Point3::new(0.0, 0.0, -3.0), 1.0, grey());
+        let moved = Translate::new(sphere, &Vec3::new(5.0, 0.0, 0.0));
+
+        assert!(
+            moved
+                .hit(
+                    &from_origin(Vec3::new(0.0, 0.0, -1.0)),
+                    0.001,
+                    f64::INFINITY
+                )
+                .is_none()
+        );
+
+        let towards = from_origin(Vec3::new(5.0, 0.0, -3.0));
+        let hit = moved.hit(&towards, 0.001, f64::INFINITY).unwrap();
+        let expected = Point3::new(5.0, 0.0, -3.0) - unit_vector(towards.direction());
+        assert!(Vec3::from(hit.hit_pos() - expected).length() < 1e-9);
+        assert!(moved.bounding_box().lower().axis(0) > 3.9);
+    }
+
+    #[test]
+    fn rotating_a_quad_90_degrees_about_y_swaps_the_axis_it_faces() {
+        let quad = Quad::boxed(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+        );
+        let along_z = from_origin(Vec3::new(0.0, 0.0, -1.0));
+        let along_x = from_origin(Vec3::new(-1.0, 0.0, 0.0));
+        assert!(quad.hit(&along_z, 0.001, f64::INFINITY).is_some());
+        assert!(quad.hit(&along_x, 0.001, f64::INFINITY).is_none());
+
+        let rotated = RotateY::new(quad, 90.0);
+        assert!(rotated.hit(&along_z, 0.001, f64::INFINITY).is_none());
+        let hit = rotated.hit(&along_x, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.normal().approx_eq(&Vec3::new(1.0, 0.0, 0.0), 1e-9));
+    }
+
+    #[test]
+    fn translate_and_rotate_forward_light_sampling_through_the_transform() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+
+        // A unit light facing +z, moved off to the side and turned to face the origin.
+        let light = || {
+            Quad::boxed(
+                Point3::new(-0.5, -0.5, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                grey(),
+            )
+        };
+        let moved = Translate::new(light(), &Vec3::new(0.0, 0.0, -3.0));
+        let turned = Translate::new(RotateY::boxed(light(), 90.0), &Vec3::new(-3.0, 0.0, 0.0));
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        // Straight on from distance 3, the density is 3^2 / (cos 0 * area 1).
+        assert!((moved.pdf_value(&origin, &Vec3::new(0.0, 0.0, -1.0)) - 9.0).abs() < 1e-9);
+        assert!((turned.pdf_value(&origin, &Vec3::new(-1.0, 0.0, 0.0)) - 9.0).abs() < 1e-9);
+        assert_eq!(moved.pdf_value(&origin, &Vec3::new(0.0, 0.0, 1.0)), 0.0);
+        assert_eq!(turned.pdf_value(&origin, &Vec3::new(1.0, 0.0, 0.0)), 0.0);
+
+        let mut rng = SmallRng::seed_from_u64(36);
+        for object in [&moved as &dyn Hittable, &turned] {
+            for _ in 0..100 {
+                let direction = object.random(&origin, &mut rng);
+                assert!(
+                    object
+                        .hit(&from_origin(direction), 0.001, f64::INFINITY)
+                        .is_some()
+                );
+                assert!(object.pdf_value(&origin, &direction) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn removing_the_outermost_sphere_shrinks_the_bounds() {
+        let mut list = HittableList::new();
+        for x in [0.0, 2.0, 10.0] {
+            list.add(sphere(Point3::new(x, 0.0, 0.0), 1.0, grey()));
+        }
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.bounding_box().upper().axis(0), 11.0);
+
+        list.remove(2);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.bounding_box().upper().axis(0), 3.0);
+        assert_eq!(list.bounding_box().lower().axis(0), -1.0);
+
+        list.clear();
+        assert!(list.is_empty());
+        assert!(list.bounding_box().is_empty());
+    }
+
+    #[test]
+    fn an_empty_list_has_an_empty_box_and_one_object_has_exactly_its_own() {
+        let mut list = HittableList::new();
+        assert!(list.bounding_box().is_empty());
+        list.rebuild_bounds();
+        assert!(list.bounding_box().is_empty());
+
+        // An empty nested list doesn't stretch the bounds either.
+        list.add(Box::new(HittableList::new()));
+        assert!(list.bounding_box().is_empty());
+
+        let ball = sphere(Point3::new(1.0, -2.0, 3.0), 0.5, grey());
+        let expected = BoundingBox::new(ball.bounding_box().lower(), ball.bounding_box().upper());
+        let mut single = HittableList::new();
+        single.add(ball);
+        assert_eq!(single.bounding_box(), &expected);
+        single.rebuild_bounds();
+        assert_eq!(single.bounding_box(), &expected);
+    }
+
+    #[test]
+    fn a_hit_record_without_coordinates_sits_at_uv_zero() {
+        let hit = HitRecord::new(
+            Point3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            grey(),
+        );
+        assert!(hit.u == 0.0 && hit.v == 0.0);
+
+        let hit = HitRecord::with_uv(
+            Point3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            grey(),
+            0.25,
+            0.75,
+        );
+        assert_eq!((hit.u, hit.v), (0.25, 0.75));
+    }
 }
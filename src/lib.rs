@@ -0,0 +1,22 @@
+pub mod animation;
+pub mod bounding;
+pub mod bvh;
+pub mod camera;
+pub mod cylinder;
+pub mod environment;
+pub mod framebuffer;
+pub mod hittable;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod quad;
+pub mod ray;
+#[cfg(feature = "serde")]
+pub mod scene;
+pub mod sphere;
+#[cfg(test)]
+mod testing;
+pub mod texture;
+pub mod triangle;
+pub mod vec3;
+pub mod world;
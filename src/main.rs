@@ -1,28 +1,16 @@
-mod bounding;
-mod bvh;
-mod camera;
-mod hittable;
-mod material;
-mod quad;
-mod ray;
-mod sphere;
-mod texture;
-mod vec3;
-
-use hittable::HittableList;
-use sphere::Sphere;
-use std::sync::Arc;
-
 use std::env;
+use std::sync::Arc;
 
-use crate::bvh::*;
-use crate::camera::*;
-use crate::hittable::*;
-use crate::material::*;
-use crate::quad::*;
-use crate::ray::*;
-use crate::texture::*;
-use crate::vec3::*;
+use tracer::bvh::*;
+use tracer::camera::*;
+use tracer::hittable::*;
+use tracer::material::*;
+use tracer::quad::*;
+use tracer::ray::*;
+use tracer::sphere::Sphere;
+use tracer::texture::*;
+use tracer::vec3::*;
+use tracer::world::Scene;
 
 fn spheres(world: &mut BvhTree) {
     let earth = Arc::new(Lambertian::new(Arc::new(ImageTexture::new("./earth.jpg"))));
@@ -34,10 +22,6 @@ fn spheres(world: &mut BvhTree) {
         Colour::new(0.4, 0.3, 0.2),
         Colour::new(0.9, 0.9, 0.9),
     ))));
-    let metalic_1 = Arc::new(Metalic::new(Colour::new(0.8, 0.2, 0.2), 0.3));
-    let metalic_2 = Arc::new(Metalic::new(Colour::new(0.9, 0.2, 0.2), 0.5));
-    let glass = Arc::new(Dielectric::new(1.50, Colour::new(0.8, 0.8, 0.9)));
-    let bubble = Arc::new(Dielectric::new(1.0 / 1.5, Colour::new(1.0, 1.0, 1.0)));
 
     world.add(Box::new(Sphere::new(
         Ray::new(Point3::new(1.0, 0.5, -1.0), Vec3::new(0.0, 0.0, 0.0), 0.0),
@@ -49,11 +33,6 @@ fn spheres(world: &mut BvhTree) {
         0.5,
         noisy.clone(),
     )));
-    // world.add(Box::new(Sphere::new(
-    //     Ray::new(Point3::new(-1.0, 0.5, -1.0), Vec3::new(0.0, 0.0, 0.0), 0.0),
-    //     0.4,
-    //     bubble.clone(),
-    // )));
     world.add(Box::new(Sphere::new(
         Ray::new(Point3::new(0.0, 0.5, -1.2), Vec3::new(0.0, 0.0, 0.0), 0.0),
         0.5,
@@ -77,9 +56,7 @@ fn quads(world: &mut BvhTree) {
         Colour::new(0.9, 0.9, 0.9),
     ))));
 
-    let green = Arc::new(Lambertian::new(Arc::new(SolidColour::new(Colour::new(
-        0.1, 1.0, 0.1,
-    )))));
+    let green = Arc::new(Lambertian::from_rgb(0.1, 1.0, 0.1));
 
     let light = Arc::new(DiffuseLight::from_colour(Colour::new(5.0, 5.0, 5.0)));
 
@@ -88,7 +65,6 @@ fn quads(world: &mut BvhTree) {
         Vec3::new(0.0, 0.0, -4.0),
         Vec3::new(0.0, 4.0, 0.0),
         lambertian.clone(),
-        |_| {},
     )));
 
     world.add(Box::new(Quad::new(
@@ -96,9 +72,6 @@ fn quads(world: &mut BvhTree) {
         Vec3::new(4.0, 0.0, 0.0),
         Vec3::new(0.0, 4.0, 0.0),
         green.clone(),
-        |x| {
-            println!("{}", x);
-        },
     )));
 
     world.add(Box::new(Quad::new(
@@ -106,7 +79,6 @@ fn quads(world: &mut BvhTree) {
         Vec3::new(0.0, 0.0, 4.0),
         Vec3::new(0.0, 4.0, 0.0),
         lambertian.clone(),
-        |_| {},
     )));
 
     world.add(Box::new(Quad::new(
@@ -114,7 +86,6 @@ fn quads(world: &mut BvhTree) {
         Vec3::new(4.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 4.0),
         light.clone(),
-        |_| {},
     )));
 }
 
@@ -128,10 +99,6 @@ fn light(world: &mut BvhTree) {
         Colour::new(0.4, 0.3, 0.2),
         Colour::new(0.9, 0.9, 0.9),
     ))));
-    let metalic_1 = Arc::new(Metalic::new(Colour::new(0.8, 0.2, 0.2), 0.3));
-    let metalic_2 = Arc::new(Metalic::new(Colour::new(0.9, 0.2, 0.2), 0.5));
-    let glass = Arc::new(Dielectric::new(1.50, Colour::new(0.8, 0.8, 0.9)));
-    let bubble = Arc::new(Dielectric::new(1.0 / 1.5, Colour::new(1.0, 1.0, 1.0)));
 
     let light = Arc::new(DiffuseLight::from_colour(Colour::new(5.0, 5.0, 5.0)));
 
@@ -140,7 +107,6 @@ fn light(world: &mut BvhTree) {
         Vec3::new(4.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 4.0),
         light.clone(),
-        |_| {},
     )));
 
     world.add(Box::new(Sphere::new(
@@ -153,11 +119,6 @@ fn light(world: &mut BvhTree) {
         0.5,
         noisy.clone(),
     )));
-    // world.add(Box::new(Sphere::new(
-    //     Ray::new(Point3::new(-1.0, 0.5, -1.0), Vec3::new(0.0, 0.0, 0.0), 0.0),
-    //     0.4,
-    //     bubble.clone(),
-    // )));
     world.add(Box::new(Sphere::new(
         Ray::new(Point3::new(0.0, 0.5, -1.2), Vec3::new(0.0, 0.0, 0.0), 0.0),
         0.5,
@@ -174,7 +135,7 @@ fn light(world: &mut BvhTree) {
     )));
 }
 
-fn boxes(world: &mut BvhTree) {
+fn boxes(world: &mut BvhTree, lights: &mut HittableList) {
     let red = Lambertian::as_arc(SolidColour::as_arc_from_rgb(0.65, 0.05, 0.05));
     let white = Lambertian::as_arc(SolidColour::as_arc_from_rgb(0.73, 0.73, 0.73));
     let green = Lambertian::as_arc(SolidColour::as_arc_from_rgb(0.12, 0.45, 0.15));
@@ -185,42 +146,42 @@ fn boxes(world: &mut BvhTree) {
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         green.clone(),
-        |_| {},
     ));
     world.add(Quad::boxed(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         red.clone(),
-        |_| {},
     ));
     world.add(Quad::boxed(
         Point3::new(343.0, 554.0, 332.0),
         Vec3::new(-130.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -105.0),
         light.clone(),
-        |_| {},
+    ));
+    lights.add(Quad::boxed(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        light.clone(),
     ));
     world.add(Quad::boxed(
         Point3::new(0.0, 0.0, 0.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 555.0),
         white.clone(),
-        |_| {},
     ));
     world.add(Quad::boxed(
         Point3::new(555.0, 555.0, 555.0),
         Vec3::new(-555.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, -555.0),
         white.clone(),
-        |_| {},
     ));
     world.add(Quad::boxed(
         Point3::new(0.0, 0.0, 555.0),
         Vec3::new(555.0, 0.0, 0.0),
         Vec3::new(0.0, 555.0, 0.0),
         white.clone(),
-        |_| {},
     ));
 
     let cube1 = Cube::boxed(
@@ -244,33 +205,81 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     let path = &args[1];
+    let scene_name = args.get(2).map_or("boxes", String::as_str);
 
     const ASPECT_RATIO: f64 = 16.0 / 9.0;
     const IMAGE_WIDTH: u64 = 800;
 
     let mut world: BvhTree = BvhTree::new();
+    let mut lights = HittableList::new();
 
-    boxes(&mut world);
+    // Each scene comes with the view it was laid out for.
+    let (look_from, look_at, vfov) = match scene_name {
+        "boxes" => {
+            boxes(&mut world, &mut lights);
+            (
+                Point3::new(278.0, 278.0, -800.0),
+                Point3::new(278.0, 278.0, 0.0),
+                40.0,
+            )
+        }
+        "spheres" => {
+            spheres(&mut world);
+            (
+                Point3::new(0.0, 1.0, 3.0),
+                Point3::new(0.0, 0.5, -1.0),
+                40.0,
+            )
+        }
+        "quads" => {
+            quads(&mut world);
+            (Point3::new(0.0, 0.0, 9.0), Point3::new(0.0, 0.0, 0.0), 80.0)
+        }
+        "light" => {
+            light(&mut world);
+            (
+                Point3::new(0.0, 1.0, 3.0),
+                Point3::new(0.0, 0.5, -1.0),
+                40.0,
+            )
+        }
+        _ => {
+            eprintln!("Unknown scene {scene_name}, expected boxes, spheres, quads or light");
+            return;
+        }
+    };
+    world.build();
+    let scene = Scene::with_lights(world, lights);
 
-    let center = Point3::new(278.0, 278.0, -800.0);
-    let look_at = Point3::new(278.0, 278.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let camera = Camera::builder()
+        .aspect_ratio(ASPECT_RATIO)
+        .image_width(IMAGE_WIDTH)
+        .vfov(vfov)
+        .look_from(look_from)
+        .look_at(look_at)
+        .up(Vec3::new(0.0, 1.0, 0.0))
+        .focus_distance(3.5)
+        .output(path)
+        .samples_per_pixel(2000)
+        .max_depth(50)
+        .build();
 
-    if let Ok(mut cam) = Camera::new(
-        ASPECT_RATIO,
-        IMAGE_WIDTH,
-        40.0,
-        center,
-        look_at,
-        vup,
-        3.5,
-        0.0,
-        path,
-    ) {
-        cam.set_samples_per_pixel(2000);
-        cam.set_max_depth(50);
-        cam.render(&world).unwrap_or_else(|err| {
-            eprintln!("Problem Rendering image: {err}");
-        });
+    let mut cam = match camera {
+        Ok(cam) => cam,
+        Err(err) => {
+            eprintln!("Problem setting up camera: {err}");
+            return;
+        }
     };
+    // The spheres scene has no lights of its own.
+    if scene_name == "spheres" {
+        cam.set_sky(
+            Colour::new(0.5, 0.7, 1.0),
+            Colour::new(1.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+    cam.render(&scene).unwrap_or_else(|err| {
+        eprintln!("Problem Rendering image: {err}");
+    });
 }
@@ -1,6 +1,6 @@
 use crate::{hittable::*, ray::*, texture::*, vec3::*};
 use core::f64;
-use rand::Rng;
+use rand::{Rng, RngCore};
 use std::sync::Arc;
 
 pub struct ScatterRecord {
@@ -20,18 +20,41 @@ impl ScatterRecord {
     pub fn scattered_ref(&self) -> &Ray {
         &self.scattered
     }
+
+    pub fn scattered(&self) -> Ray {
+        self.scattered
+    }
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord>;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord>;
 
-    fn emit(&self, u: f64, v: f64, p: &Point3) -> Option<Colour> {
-        None
+    // Light leaving the hit back along ray, which lets emission depend on the viewing angle.
+    // The hit record carries the u, v and point an emitted(u, v, p) signature would take.
+    fn emitted(&self, _ray: &Ray, _hit_record: &HitRecord) -> Colour {
+        Colour::new(0.0, 0.0, 0.0)
     }
 
-    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
+    fn scatter_pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _scatter_ray: &Ray) -> f64 {
         0.0
     }
+
+    // Base colour at the hit without any lighting, for the albedo debug view. Materials with
+    // no surface colour of their own, like lights, stay black.
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        Colour::new(0.0, 0.0, 0.0)
+    }
+
+    // Normal the material shades with, which differs from the geometric one under normal and
+    // bump maps.
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        hit_record.normal()
+    }
 }
 
 pub struct Lambertian {
@@ -46,14 +69,28 @@ impl Lambertian {
     pub fn as_arc(albedo: Arc<dyn Texture>) -> Arc<Lambertian> {
         Arc::new(Lambertian { albedo })
     }
+
+    pub fn from_colour(colour: Colour) -> Lambertian {
+        Lambertian::new(SolidColour::as_arc(colour))
+    }
+
+    pub fn from_rgb(r: f64, g: f64, b: f64) -> Lambertian {
+        Lambertian::from_colour(Colour::new(r, g, b))
+    }
+
+    pub fn as_arc_from_colour(colour: Colour) -> Arc<Lambertian> {
+        Arc::new(Lambertian::from_colour(colour))
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
-        let mut scatter_direction = hit_record.normal() + Vec3::random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal();
-        }
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let scatter_direction = Vec3::random_cosine_direction(rng, &hit_record.normal());
         Some(ScatterRecord {
             attenuation: self
                 .albedo
@@ -62,7 +99,7 @@ impl Material for Lambertian {
         })
     }
 
-    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
+    fn scatter_pdf(&self, _ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
         let cos_theta = dot(hit_record.normal(), unit_vector(scatter_ray.direction()));
         if cos_theta < 0.0 {
             0.0
@@ -70,44 +107,319 @@ impl Material for Lambertian {
             cos_theta / f64::consts::PI
         }
     }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+    }
+}
+
+// Rough diffuse surface using the qualitative Oren-Nayar model, sigma being the standard
+// deviation of the microfacet slopes in radians. Its reflectance depends on both directions,
+// so it doesn't report a scatter pdf and is left out of direct light sampling.
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<dyn Texture>, sigma: f64) -> OrenNayar {
+        let sigma_squared = sigma * sigma;
+        OrenNayar {
+            albedo,
+            a: 1.0 - 0.5 * sigma_squared / (sigma_squared + 0.33),
+            b: 0.45 * sigma_squared / (sigma_squared + 0.09),
+        }
+    }
+
+    pub fn as_arc(albedo: Arc<dyn Texture>, sigma: f64) -> Arc<OrenNayar> {
+        Arc::new(OrenNayar::new(albedo, sigma))
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let normal = hit_record.normal();
+        let scatter_direction = Vec3::random_cosine_direction(rng, &normal);
+
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos());
+
+        // Directions are cosine sampled as for Lambertian, which leaves the bracketed
+        // Oren-Nayar term as the only weight on top of the albedo.
+        let outgoing = -unit_vector(ray.direction());
+        let incoming = unit_vector(scatter_direction);
+        let cos_o = dot(normal, outgoing).clamp(0.0, 1.0);
+        let cos_i = dot(normal, incoming).clamp(0.0, 1.0);
+
+        let mut factor = self.a;
+        if self.b > 0.0 && cos_o < 1.0 && cos_i < 1.0 && cos_o > 0.0 && cos_i > 0.0 {
+            let tangent_o = outgoing - cos_o * normal;
+            let tangent_i = incoming - cos_i * normal;
+            let cos_phi = dot(unit_vector(tangent_o), unit_vector(tangent_i)).max(0.0);
+
+            let (cos_alpha, cos_beta) = (cos_o.min(cos_i), cos_o.max(cos_i));
+            let sin_alpha = f64::sqrt(1.0 - cos_alpha * cos_alpha);
+            let tan_beta = f64::sqrt(1.0 - cos_beta * cos_beta) / cos_beta;
+
+            factor += self.b * cos_phi * sin_alpha * tan_beta;
+        }
+
+        Some(ScatterRecord {
+            attenuation: albedo * factor,
+            scattered: Ray::new(hit_record.hit_pos(), scatter_direction, ray.time()),
+        })
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+    }
 }
 
+// Albedo is sampled from a texture at the hit. Fuzz is either a fixed amount or read from the
+// luminance of a texture, so roughness can vary across a surface like brushed metal.
 pub struct Metalic {
-    albedo: Colour,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
+    fuzz_texture: Option<Arc<dyn Texture>>,
 }
 
 impl Metalic {
     pub fn new(albedo: Colour, fuzz: f64) -> Metalic {
+        Metalic::from_texture(Arc::new(SolidColour::new(albedo)), fuzz)
+    }
+
+    pub fn as_arc(albedo: Colour, fuzz: f64) -> Arc<Metalic> {
+        Arc::new(Metalic::new(albedo, fuzz))
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Metalic {
         Metalic {
             albedo,
             fuzz: fuzz.clamp(0.0, 1.0),
+            fuzz_texture: None,
         }
     }
 
-    pub fn as_arc(albedo: Colour, fuzz: f64) -> Arc<Metalic> {
-        Arc::new(Metalic {
+    pub fn with_fuzz_texture(albedo: Arc<dyn Texture>, fuzz: Arc<dyn Texture>) -> Metalic {
+        Metalic {
             albedo,
-            fuzz: fuzz.clamp(0.0, 1.0),
-        })
+            fuzz: 0.0,
+            fuzz_texture: Some(fuzz),
+        }
+    }
+
+    pub fn as_arc_with_fuzz_texture(
+        albedo: Arc<dyn Texture>,
+        fuzz: Arc<dyn Texture>,
+    ) -> Arc<Metalic> {
+        Arc::new(Metalic::with_fuzz_texture(albedo, fuzz))
+    }
+
+    fn fuzz_at(&self, hit_record: &HitRecord) -> f64 {
+        match &self.fuzz_texture {
+            Some(texture) => texture
+                .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+                .luminance()
+                .clamp(0.0, 1.0),
+            None => self.fuzz,
+        }
     }
 }
 
 impl Material for Metalic {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
         let reflected = ray.direction().reflect(&hit_record.normal())
-            + (self.fuzz * Vec3::random_unit_vector());
+            + (self.fuzz_at(hit_record) * Vec3::random_unit_vector(rng));
+
+        // Fuzz can push the reflection below the surface, the metal absorbs those.
+        if dot(reflected, hit_record.normal()) <= 0.0 {
+            return None;
+        }
 
         Some(ScatterRecord {
-            attenuation: self.albedo,
+            attenuation: self
+                .albedo
+                .value(hit_record.u, hit_record.v, hit_record.hit_pos()),
             scattered: Ray::new(hit_record.hit_pos(), reflected, ray.time()),
         })
     }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+    }
+}
+
+// GGX microfacet metal. Reflectance at normal incidence comes from base_colour and rises
+// towards white at grazing angles following Schlick's approximation.
+pub struct RoughConductor {
+    base_colour: Colour,
+    roughness: f64,
+}
+
+impl RoughConductor {
+    pub fn new(base_colour: Colour, roughness: f64) -> RoughConductor {
+        RoughConductor {
+            base_colour,
+            roughness: roughness.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn as_arc(base_colour: Colour, roughness: f64) -> Arc<RoughConductor> {
+        Arc::new(RoughConductor::new(base_colour, roughness))
+    }
+
+    // Keeps a roughness of zero from dividing by zero, it still behaves as a mirror.
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(1e-4)
+    }
+
+    fn fresnel(&self, cosine: f64) -> Colour {
+        let weight = f64::powf(1.0 - cosine, 5.0);
+        self.base_colour * (1.0 - weight) + Colour::new(weight, weight, weight)
+    }
+
+    // Smith's masking term for one direction.
+    fn g1(&self, n_dot_v: f64) -> f64 {
+        let alpha_squared = self.alpha() * self.alpha();
+        2.0 * n_dot_v
+            / (n_dot_v + f64::sqrt(alpha_squared + (1.0 - alpha_squared) * n_dot_v * n_dot_v))
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let normal = hit_record.normal();
+        let outgoing = -unit_vector(ray.direction());
+
+        // Sample a microfacet normal in proportion to D(h) * cos(theta_h).
+        let r1: f64 = rng.random();
+        let r2: f64 = rng.random();
+        let tan_squared = self.alpha() * self.alpha() * r1 / (1.0 - r1);
+        let cos_theta = 1.0 / f64::sqrt(1.0 + tan_squared);
+        let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * f64::consts::PI * r2;
+
+        let (u, v) = orthonormal_basis(normal);
+        let half = (f64::cos(phi) * sin_theta * u)
+            + (f64::sin(phi) * sin_theta * v)
+            + (cos_theta * normal);
+
+        let direction = (-outgoing).reflect(&half);
+
+        let n_dot_o = dot(normal, outgoing);
+        let n_dot_i = dot(normal, direction);
+        let o_dot_h = dot(outgoing, half);
+        if n_dot_o <= 0.0 || n_dot_i <= 0.0 || o_dot_h <= 0.0 {
+            return None;
+        }
+
+        // BRDF * cos / pdf, with the distribution term cancelling against the sampling density.
+        let weight = self.g1(n_dot_o) * self.g1(n_dot_i) * o_dot_h / (n_dot_o * cos_theta);
+
+        Some(ScatterRecord {
+            attenuation: self.fresnel(o_dot_h) * weight,
+            scattered: Ray::new(hit_record.hit_pos(), direction, ray.time()),
+        })
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        self.base_colour
+    }
 }
 
+// Albedo tints transmitted rays only, reflections off the surface pick up reflection_tint
+// instead, which is white unless set.
 pub struct Dielectric {
     refractive_index: f64,
     albedo: Colour,
+    reflection_tint: Colour,
+    absorption: Colour,
+    dispersion: Option<Dispersion>,
+}
+
+// Visible light is split into this many equal bands when a dispersive dielectric needs to
+// pick a wavelength for a path.
+const DISPERSION_BANDS: usize = 8;
+
+// Cauchy's equation n = a + b / wavelength^2, with wavelengths in micrometres, along with the
+// centre wavelength of each band and the tint that carries its share of white light.
+struct Dispersion {
+    cauchy_a: f64,
+    cauchy_b: f64,
+    bands: Vec<(f64, Colour)>,
+}
+
+impl Dispersion {
+    fn new(refractive_index: f64, cauchy_b: f64) -> Dispersion {
+        let band_width = 0.3 / DISPERSION_BANDS as f64;
+        let wavelengths: Vec<f64> = (0..DISPERSION_BANDS)
+            .map(|band| 0.4 + (band as f64 + 0.5) * band_width)
+            .collect();
+
+        // Scale the tints so that, averaged over a uniform choice of band, they come to white.
+        let colours: Vec<Colour> = wavelengths.iter().map(|&w| wavelength_colour(w)).collect();
+        let total = colours
+            .iter()
+            .fold(Colour::new(0.0, 0.0, 0.0), |sum, &colour| sum + colour);
+        let scale = Colour::new(
+            DISPERSION_BANDS as f64 / total.r(),
+            DISPERSION_BANDS as f64 / total.g(),
+            DISPERSION_BANDS as f64 / total.b(),
+        );
+
+        Dispersion {
+            cauchy_a: refractive_index - cauchy_b / (0.55 * 0.55),
+            cauchy_b,
+            bands: wavelengths
+                .into_iter()
+                .zip(colours)
+                .map(|(wavelength, colour)| (wavelength, colour * scale))
+                .collect(),
+        }
+    }
+
+    fn refractive_index(&self, wavelength: f64) -> f64 {
+        self.cauchy_a + self.cauchy_b / (wavelength * wavelength)
+    }
+}
+
+// Rough piecewise fit of the visible spectrum to RGB, wavelength in micrometres.
+fn wavelength_colour(wavelength: f64) -> Colour {
+    let nm = wavelength * 1000.0;
+    let (r, g, b) = if nm < 440.0 {
+        ((440.0 - nm) / 60.0, 0.0, 1.0)
+    } else if nm < 490.0 {
+        (0.0, (nm - 440.0) / 50.0, 1.0)
+    } else if nm < 510.0 {
+        (0.0, 1.0, (510.0 - nm) / 20.0)
+    } else if nm < 580.0 {
+        ((nm - 510.0) / 70.0, 1.0, 0.0)
+    } else if nm < 645.0 {
+        (1.0, (645.0 - nm) / 65.0, 0.0)
+    } else {
+        (1.0, 0.0, 0.0)
+    };
+    Colour::new(r, g, b)
 }
 
 impl Dielectric {
@@ -115,29 +427,94 @@ impl Dielectric {
         Dielectric {
             refractive_index,
             albedo,
+            reflection_tint: Colour::new(1.0, 1.0, 1.0),
+            absorption: Colour::new(0.0, 0.0, 0.0),
+            dispersion: None,
         }
     }
 
     pub fn as_arc(refractive_index: f64, albedo: Colour) -> Arc<Dielectric> {
-        Arc::new(Dielectric {
+        Arc::new(Dielectric::new(refractive_index, albedo))
+    }
+
+    pub fn new_with_absorption(refractive_index: f64, absorption: Colour) -> Dielectric {
+        Dielectric {
             refractive_index,
-            albedo,
-        })
+            albedo: Colour::new(1.0, 1.0, 1.0),
+            reflection_tint: Colour::new(1.0, 1.0, 1.0),
+            absorption,
+            dispersion: None,
+        }
+    }
+
+    // Refractive index varies with wavelength following Cauchy's equation, matching the given
+    // index at 550nm. Each path picks a single band the first time it enters dispersive glass
+    // and keeps it through any further glass, so colours separate as they would in a prism.
+    // Typical values of cauchy_b are around 0.004 for crown glass and 0.01 for flint.
+    pub fn new_dispersive(refractive_index: f64, cauchy_b: f64, albedo: Colour) -> Dielectric {
+        Dielectric {
+            dispersion: Some(Dispersion::new(refractive_index, cauchy_b)),
+            ..Dielectric::new(refractive_index, albedo)
+        }
+    }
+
+    pub fn with_reflection_tint(self, reflection_tint: Colour) -> Dielectric {
+        Dielectric {
+            reflection_tint,
+            ..self
+        }
+    }
+
+    // Beer-Lambert falloff for a path of the given length through the medium.
+    fn transmittance(&self, distance: f64) -> Colour {
+        Colour::new(
+            f64::exp(-self.absorption.r() * distance),
+            f64::exp(-self.absorption.g() * distance),
+            f64::exp(-self.absorption.b() * distance),
+        )
     }
 
-    fn reflectance(&self, cosine: f64) -> f64 {
-        let mut r0 = (1.0 - self.refractive_index) / (1.0 + self.refractive_index);
+    fn reflectance(refractive_index: f64, cosine: f64) -> f64 {
+        let mut r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
         r0 = r0 * r0;
         r0 + (1.0 - r0) * f64::powf(1.0 - cosine, 5.0)
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        // A path that hasn't picked a band yet picks one here and is tinted by it.
+        let (refractive_index, wavelength, tint) = match (&self.dispersion, ray.wavelength()) {
+            (Some(dispersion), Some(wavelength)) => (
+                dispersion.refractive_index(wavelength),
+                Some(wavelength),
+                Colour::new(1.0, 1.0, 1.0),
+            ),
+            (Some(dispersion), None) => {
+                let (wavelength, tint) =
+                    dispersion.bands[rng.random_range(0..dispersion.bands.len())];
+                (
+                    dispersion.refractive_index(wavelength),
+                    Some(wavelength),
+                    tint,
+                )
+            }
+            (None, wavelength) => (
+                self.refractive_index,
+                wavelength,
+                Colour::new(1.0, 1.0, 1.0),
+            ),
+        };
+
         let ri = if hit_record.front_face() {
-            1.0 / self.refractive_index
+            1.0 / refractive_index
         } else {
-            self.refractive_index
+            refractive_index
         };
 
         let unit_direction = unit_vector(ray.direction());
@@ -146,49 +523,961 @@ impl Material for Dielectric {
 
         let cant_refract = (ri * sin_theta) > 1.0;
 
-        let direction;
-        let mut rng = rand::rng();
-        if cant_refract || self.reflectance(cos_theta) > rng.random() {
-            direction = unit_direction.reflect(&hit_record.normal());
+        let (direction, surface_tint) = if cant_refract
+            || Dielectric::reflectance(refractive_index, cos_theta) > rng.random()
+        {
+            (
+                unit_direction.reflect(&hit_record.normal()),
+                self.reflection_tint,
+            )
         } else {
-            direction = unit_direction.refract(&hit_record.normal(), ri)
-        }
+            (
+                unit_direction.refract(&hit_record.normal(), ri),
+                self.albedo,
+            )
+        };
+
+        // Hitting a back face means the incoming ray travelled through the medium to get here.
+        let attenuation = if hit_record.front_face() {
+            surface_tint * tint
+        } else {
+            let distance = hit_record.t * ray.direction().length();
+            surface_tint * tint * self.transmittance(distance)
+        };
 
         Some(ScatterRecord {
-            attenuation: self.albedo,
-            scattered: Ray::new(hit_record.hit_pos(), direction, ray.time()),
+            attenuation,
+            scattered: Ray::new(hit_record.hit_pos(), direction, ray.time())
+                .with_wavelength(wavelength),
         })
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Colour {
+        self.albedo
+    }
 }
 
 pub struct DiffuseLight {
     texture: Arc<dyn Texture>,
+    intensity: f64,
 }
 
 impl DiffuseLight {
     pub fn new(texture: Arc<dyn Texture>) -> DiffuseLight {
-        DiffuseLight { texture }
+        DiffuseLight::with_intensity(texture, 1.0)
+    }
+
+    // Emission is the texture's colour scaled by intensity, so a texture in the usual 0-1
+    // range can still drive a bright light.
+    pub fn with_intensity(texture: Arc<dyn Texture>, intensity: f64) -> DiffuseLight {
+        DiffuseLight { texture, intensity }
     }
 
     pub fn from_colour(colour: Colour) -> DiffuseLight {
-        DiffuseLight {
-            texture: Arc::new(SolidColour::new(colour)),
-        }
+        DiffuseLight::new(Arc::new(SolidColour::new(colour)))
     }
 
     pub fn as_arc_from_colour(colour: Colour) -> Arc<DiffuseLight> {
-        Arc::new(DiffuseLight {
-            texture: SolidColour::as_arc(colour),
-        })
+        Arc::new(DiffuseLight::from_colour(colour))
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
         None
     }
 
-    fn emit(&self, u: f64, v: f64, p: &Point3) -> Option<Colour> {
-        Some(self.texture.value(u, v, *p))
+    fn emitted(&self, _ray: &Ray, hit_record: &HitRecord) -> Colour {
+        self.texture
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+            * self.intensity
+    }
+}
+
+// Emits like DiffuseLight, but only in a cone around the surface normal. Emission is full
+// within inner_angle of the normal, fades out smoothly towards outer_angle, and is zero beyond
+// it. Both angles are in degrees. The back face emits too unless turned off.
+pub struct SpotLight {
+    texture: Arc<dyn Texture>,
+    intensity: f64,
+    cos_inner: f64,
+    cos_outer: f64,
+    back_face: bool,
+}
+
+impl SpotLight {
+    pub fn new(
+        texture: Arc<dyn Texture>,
+        intensity: f64,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        let outer_angle = outer_angle.clamp(0.0, 90.0);
+        SpotLight {
+            texture,
+            intensity,
+            cos_inner: inner_angle.clamp(0.0, outer_angle).to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            back_face: true,
+        }
+    }
+
+    pub fn from_colour(colour: Colour, inner_angle: f64, outer_angle: f64) -> SpotLight {
+        SpotLight::new(
+            Arc::new(SolidColour::new(colour)),
+            1.0,
+            inner_angle,
+            outer_angle,
+        )
+    }
+
+    pub fn as_arc_from_colour(
+        colour: Colour,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Arc<SpotLight> {
+        Arc::new(SpotLight::from_colour(colour, inner_angle, outer_angle))
+    }
+
+    pub fn with_back_face(self, back_face: bool) -> SpotLight {
+        SpotLight { back_face, ..self }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Material for SpotLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, ray: &Ray, hit_record: &HitRecord) -> Colour {
+        if !self.back_face && !hit_record.front_face() {
+            return Colour::new(0.0, 0.0, 0.0);
+        }
+
+        // The recorded normal faces the incoming ray, so this is the angle off either face.
+        let cos_theta = dot(-unit_vector(ray.direction()), hit_record.normal());
+
+        self.texture
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+            * (self.intensity * self.falloff(cos_theta))
+    }
+}
+
+// Phase function for participating media, given to a ConstantMedium as its material.
+// Scattered directions are drawn from the Henyey-Greenstein distribution around the incoming
+// direction, ignoring the surface normal. g above zero favours forward scattering, below zero
+// back scattering, and zero scatters evenly in every direction.
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Arc<dyn Texture>, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+
+    pub fn from_colour(albedo: Colour, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein::new(Arc::new(SolidColour::new(albedo)), g)
+    }
+
+    pub fn as_arc(albedo: Arc<dyn Texture>, g: f64) -> Arc<HenyeyGreenstein> {
+        Arc::new(HenyeyGreenstein::new(albedo, g))
+    }
+
+    // Inverts the distribution's CDF for the cosine of the angle to the incoming direction.
+    fn sample_cos_theta(&self, xi: f64) -> f64 {
+        let g = self.g;
+        if f64::abs(g) < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+
+        let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        ((1.0 + g * g - term * term) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let forward = unit_vector(ray.direction());
+        let cos_theta = self.sample_cos_theta(rng.random());
+        let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * f64::consts::PI * rng.random::<f64>();
+
+        let (u, v) = orthonormal_basis(forward);
+        let direction = (f64::cos(phi) * sin_theta * u)
+            + (f64::sin(phi) * sin_theta * v)
+            + (cos_theta * forward);
+
+        Some(ScatterRecord {
+            attenuation: self
+                .albedo
+                .value(hit_record.u, hit_record.v, hit_record.hit_pos()),
+            scattered: Ray::new(hit_record.hit_pos(), direction, ray.time()),
+        })
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+    }
+}
+
+// Diffuse base under a clear dielectric coat, like paint or plastic. Each ray either reflects
+// off the coat, with the Fresnel probability for its angle, or passes through to scatter off
+// the base. Like MixMaterial it has no single scatter density, so it stays out of light
+// sampling.
+pub struct CoatedDiffuse {
+    albedo: Arc<dyn Texture>,
+    refractive_index: f64,
+}
+
+impl CoatedDiffuse {
+    pub fn new(albedo: Arc<dyn Texture>, refractive_index: f64) -> CoatedDiffuse {
+        CoatedDiffuse {
+            albedo,
+            refractive_index,
+        }
+    }
+
+    pub fn as_arc(albedo: Arc<dyn Texture>, refractive_index: f64) -> Arc<CoatedDiffuse> {
+        Arc::new(CoatedDiffuse::new(albedo, refractive_index))
+    }
+}
+
+impl Material for CoatedDiffuse {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let unit_direction = unit_vector(ray.direction());
+        let normal = hit_record.normal();
+        let cos_theta = dot(-unit_direction, normal).clamp(0.0, 1.0);
+
+        if Dielectric::reflectance(self.refractive_index, cos_theta) > rng.random() {
+            return Some(ScatterRecord {
+                attenuation: Colour::new(1.0, 1.0, 1.0),
+                scattered: Ray::new(
+                    hit_record.hit_pos(),
+                    unit_direction.reflect(&normal),
+                    ray.time(),
+                ),
+            });
+        }
+
+        let scatter_direction = Vec3::random_cosine_direction(rng, &normal);
+        Some(ScatterRecord {
+            attenuation: self
+                .albedo
+                .value(hit_record.u, hit_record.v, hit_record.hit_pos()),
+            scattered: Ray::new(hit_record.hit_pos(), scatter_direction, ray.time()),
+        })
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.albedo
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos())
+    }
+}
+
+// Scatters as material a with probability ratio and as b otherwise, for surfaces that are
+// part diffuse and part specular. A blend can't be split into a single scatter density, so
+// it only reports one, and takes part in light sampling, when the ratio picks one material
+// outright.
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    ratio: f64,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, ratio: f64) -> MixMaterial {
+        MixMaterial {
+            a,
+            b,
+            ratio: ratio.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn as_arc(a: Arc<dyn Material>, b: Arc<dyn Material>, ratio: f64) -> Arc<MixMaterial> {
+        Arc::new(MixMaterial::new(a, b, ratio))
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        if rng.random::<f64>() < self.ratio {
+            self.a.scatter(ray, hit_record, rng)
+        } else {
+            self.b.scatter(ray, hit_record, rng)
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, hit_record: &HitRecord) -> Colour {
+        self.a.emitted(ray, hit_record) * self.ratio
+            + self.b.emitted(ray, hit_record) * (1.0 - self.ratio)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
+        if self.ratio >= 1.0 {
+            self.a.scatter_pdf(ray, hit_record, scatter_ray)
+        } else if self.ratio <= 0.0 {
+            self.b.scatter_pdf(ray, hit_record, scatter_ray)
+        } else {
+            0.0
+        }
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.a.albedo(hit_record) * self.ratio + self.b.albedo(hit_record) * (1.0 - self.ratio)
+    }
+}
+
+// Wraps another material and bends the shading normal using a tangent space normal map,
+// with the usual encoding of each component from -1..1 into 0..1. Hits on shapes that don't
+// provide a tangent use an arbitrary frame around the normal, so only a flat map is exact.
+pub struct NormalMap {
+    material: Arc<dyn Material>,
+    map: Arc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(material: Arc<dyn Material>, map: Arc<dyn Texture>) -> NormalMap {
+        NormalMap { material, map }
+    }
+
+    pub fn as_arc(material: Arc<dyn Material>, map: Arc<dyn Texture>) -> Arc<NormalMap> {
+        Arc::new(NormalMap::new(material, map))
+    }
+
+    fn perturbed(&self, hit_record: &HitRecord) -> HitRecord {
+        let (tangent, bitangent) = hit_record.tangent_frame();
+        let normal = hit_record.normal();
+
+        let sample = self
+            .map
+            .value(hit_record.u, hit_record.v, hit_record.hit_pos());
+        let x = 2.0 * sample.r() - 1.0;
+        let y = 2.0 * sample.g() - 1.0;
+        let z = 2.0 * sample.b() - 1.0;
+
+        let mut record = hit_record.clone();
+        record.update_record(
+            hit_record.hit_pos(),
+            unit_vector(x * tangent + y * bitangent + z * normal),
+            hit_record.t,
+        );
+        record
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter(ray, &self.perturbed(hit_record), rng)
+    }
+
+    fn emitted(&self, ray: &Ray, hit_record: &HitRecord) -> Colour {
+        self.material.emitted(ray, hit_record)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
+        self.material
+            .scatter_pdf(ray, &self.perturbed(hit_record), scatter_ray)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.material.albedo(hit_record)
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.material.shading_normal(&self.perturbed(hit_record))
+    }
+}
+
+// Step in u and v used to finite difference the height texture.
+const BUMP_DELTA: f64 = 1e-3;
+
+// Wraps another material and tilts the shading normal away from the slope of a grayscale
+// height texture, read as luminance. Strength scales the height change per unit of UV. Like
+// NormalMap, hits without a tangent use an arbitrary frame around the normal.
+pub struct BumpMap {
+    material: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    strength: f64,
+}
+
+impl BumpMap {
+    pub fn new(material: Arc<dyn Material>, height: Arc<dyn Texture>, strength: f64) -> BumpMap {
+        BumpMap {
+            material,
+            height,
+            strength,
+        }
+    }
+
+    pub fn as_arc(
+        material: Arc<dyn Material>,
+        height: Arc<dyn Texture>,
+        strength: f64,
+    ) -> Arc<BumpMap> {
+        Arc::new(BumpMap::new(material, height, strength))
+    }
+
+    fn perturbed(&self, hit_record: &HitRecord) -> HitRecord {
+        let (tangent, bitangent) = hit_record.tangent_frame();
+        let normal = hit_record.normal();
+
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.hit_pos());
+        let height = |u: f64, v: f64| self.height.value(u, v, p).luminance();
+        let dh_du = (height(u + BUMP_DELTA, v) - height(u - BUMP_DELTA, v)) / (2.0 * BUMP_DELTA);
+        let dh_dv = (height(u, v + BUMP_DELTA) - height(u, v - BUMP_DELTA)) / (2.0 * BUMP_DELTA);
+
+        // The normal of the height field n - h'(u) t - h'(v) b, leaning away from uphill.
+        let bumped = normal - self.strength * (dh_du * tangent + dh_dv * bitangent);
+
+        let mut record = hit_record.clone();
+        record.update_record(hit_record.hit_pos(), unit_vector(bumped), hit_record.t);
+        record
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter(ray, &self.perturbed(hit_record), rng)
+    }
+
+    fn emitted(&self, ray: &Ray, hit_record: &HitRecord) -> Colour {
+        self.material.emitted(ray, hit_record)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, scatter_ray: &Ray) -> f64 {
+        self.material
+            .scatter_pdf(ray, &self.perturbed(hit_record), scatter_ray)
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Colour {
+        self.material.albedo(hit_record)
+    }
+
+    fn shading_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        self.material.shading_normal(&self.perturbed(hit_record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use rand::SeedableRng;
+    use rand::rngs::SmallRng;
+
+    #[test]
+    fn lambertian_pdf_integrates_to_one_over_the_hemisphere() {
+        let material = Lambertian::as_arc_from_colour(Colour::new(0.5, 0.5, 0.5));
+        let normal = unit_vector(Vec3::new(1.0, 2.0, -0.5));
+        let hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), normal, 1.0, material.clone());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), -normal, 0.0);
+
+        // Uniform directions over the whole sphere have a density of 1 / 4pi.
+        let mut rng = SmallRng::seed_from_u64(54);
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| {
+                let direction = Vec3::random_unit_vector(&mut rng);
+                let scattered = Ray::new(hit.hit_pos(), direction, 0.0);
+                material.scatter_pdf(&ray, &hit, &scattered) * 4.0 * f64::consts::PI
+            })
+            .sum();
+
+        let integral = total / samples as f64;
+        assert!(
+            (integral - 1.0).abs() < 0.01,
+            "pdf integrates to {integral}"
+        );
+    }
+
+    #[test]
+    fn normal_map_bends_hits_without_a_tangent() {
+        let grey = grey();
+        // Leans half way over towards the tangent, whichever frame that ends up being.
+        let tilt = Arc::new(SolidColour::new(Colour::new(0.85, 0.5, 0.85)));
+        let material = NormalMap::as_arc(grey.clone(), tilt);
+
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), normal, 1.0, grey);
+        let bent = material.shading_normal(&hit);
+
+        assert!((bent.length() - 1.0).abs() < 1e-9);
+        assert!((dot(bent, normal) - f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+    }
+
+    // Where a ray from the origin along -z leaves a medium after travelling distance through it.
+    fn exit_hit(material: Arc<dyn Material>, distance: f64) -> (Ray, HitRecord) {
+        let inside = ray(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let outward = Vec3::new(0.0, 0.0, -1.0);
+        let mut hit = HitRecord::new(inside.at(distance), outward, distance, material);
+        hit.set_face_normal(&inside, outward);
+        (inside, hit)
+    }
+
+    #[test]
+    fn a_thicker_slab_of_coloured_glass_absorbs_more() {
+        let glass = Arc::new(Dielectric::new_with_absorption(
+            1.5,
+            Colour::new(0.1, 0.5, 1.0),
+        ));
+        let mut rng = SmallRng::seed_from_u64(16);
+        let mut through = |distance: f64| {
+            let (ray, hit) = exit_hit(glass.clone(), distance);
+            assert!(!hit.front_face());
+            glass.scatter(&ray, &hit, &mut rng).unwrap().attenuation()
+        };
+
+        let (thin, thick) = (through(0.5), through(2.0));
+        assert!(thin.approx_eq(
+            &Colour::new((-0.05f64).exp(), (-0.25f64).exp(), (-0.5f64).exp()),
+            1e-12
+        ));
+        assert!(thick.r() < thin.r() && thick.g() < thin.g() && thick.b() < thin.b());
+
+        let clear = Arc::new(Dielectric::new(1.5, Colour::new(1.0, 1.0, 1.0)));
+        let (ray, hit) = exit_hit(clear.clone(), 2.0);
+        let attenuation = clear.scatter(&ray, &hit, &mut rng).unwrap().attenuation();
+        assert_eq!(attenuation, Colour::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn doubling_a_light_intensity_doubles_its_emission() {
+        let panel: Arc<dyn Texture> = Arc::new(CheckerTexture::new_uv(
+            0.5,
+            Colour::new(0.2, 0.4, 0.6),
+            Colour::new(0.9, 0.1, 0.3),
+        ));
+        let glow = |intensity: f64, u: f64, v: f64| {
+            let light: Arc<dyn Material> =
+                Arc::new(DiffuseLight::with_intensity(panel.clone(), intensity));
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            let hit = HitRecord::with_uv(Point3::new(0.0, 0.0, 0.0), normal, 1.0, light, u, v);
+            let ray = ray(Point3::new(0.0, 0.0, 1.0), -normal);
+            hit.material_ref().emitted(&ray, &hit)
+        };
+
+        for (u, v) in [(0.25, 0.25), (0.75, 0.25)] {
+            assert_eq!(glow(2.0, u, v), glow(1.0, u, v) * 2.0);
+        }
+        assert_ne!(glow(1.0, 0.25, 0.25), glow(1.0, 0.75, 0.25));
+    }
+
+    // A ray coming down at 45 degrees onto an upward facing hit at the origin.
+    fn hit_at_45_degrees(material: Arc<dyn Material>) -> (Ray, HitRecord) {
+        let incoming = ray(Point3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, material);
+        hit.set_face_normal(&incoming, up);
+        (incoming, hit)
+    }
+
+    #[test]
+    fn rough_conductor_spreads_reflections_with_roughness() {
+        let mirror_direction = unit_vector(Vec3::new(1.0, 1.0, 0.0));
+        let mut rng = SmallRng::seed_from_u64(50);
+        let mut spread = |roughness: f64| {
+            let material = RoughConductor::as_arc(Colour::new(0.9, 0.8, 0.6), roughness);
+            let (ray, hit) = hit_at_45_degrees(material.clone());
+            let deviations: Vec<f64> = (0..1000)
+                .filter_map(|_| material.scatter(&ray, &hit, &mut rng))
+                .map(|scatter| {
+                    (unit_vector(scatter.scattered().direction()) - mirror_direction)
+                        .length_squared()
+                })
+                .collect();
+            deviations.iter().sum::<f64>() / deviations.len() as f64
+        };
+
+        let (smooth, rough) = (spread(0.0), spread(0.8));
+        assert!(smooth < 1e-4, "roughness 0 spreads by {smooth}");
+        assert!(rough > 0.05, "roughness 0.8 spreads by only {rough}");
+    }
+
+    #[test]
+    fn oren_nayar_is_brighter_than_lambertian_back_towards_a_grazing_view() {
+        let albedo = Colour::new(0.6, 0.5, 0.4);
+        let clay = OrenNayar::as_arc(Arc::new(SolidColour::new(albedo)), 1.0);
+        let matte = Lambertian::as_arc_from_colour(albedo);
+
+        // Viewed 80 degrees away from the normal, keeping light arriving from near the viewer.
+        let (sin, cos) = 80f64.to_radians().sin_cos();
+        let towards_viewer = Vec3::new(-sin, cos, 0.0);
+        let incoming = ray(Point3::from(towards_viewer), -towards_viewer);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut rng = SmallRng::seed_from_u64(51);
+        let mut retro_brightness = |material: Arc<dyn Material>| {
+            let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, material.clone());
+            hit.set_face_normal(&incoming, up);
+            let near_viewer: Vec<f64> = (0..200_000)
+                .filter_map(|_| material.scatter(&incoming, &hit, &mut rng))
+                .filter(|scatter| {
+                    dot(unit_vector(scatter.scattered().direction()), towards_viewer) > 0.95
+                })
+                .map(|scatter| scatter.attenuation().luminance())
+                .collect();
+            assert!(near_viewer.len() > 100);
+            near_viewer.iter().sum::<f64>() / near_viewer.len() as f64
+        };
+
+        let (rough, flat) = (retro_brightness(clay), retro_brightness(matte));
+        assert!(
+            rough > 1.5 * flat,
+            "Oren-Nayar {rough} against Lambertian {flat}"
+        );
+    }
+
+    #[test]
+    fn a_flat_normal_map_leaves_scattering_unchanged() {
+        let base = Lambertian::as_arc_from_colour(Colour::new(0.7, 0.3, 0.2));
+        let flat = Arc::new(SolidColour::new(Colour::new(0.5, 0.5, 1.0)));
+        let mapped: Arc<dyn Material> = NormalMap::as_arc(base.clone(), flat);
+
+        let normal = unit_vector(Vec3::new(0.3, 1.0, -0.2));
+        let incoming = ray(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.1, -1.0, 0.0));
+        let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), normal, 2.0, base.clone());
+        hit.set_face_normal(&incoming, normal);
+        let mut with_frame = hit.clone();
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        with_frame.set_tangent_frame(tangent, bitangent);
+
+        for hit in [hit, with_frame] {
+            assert!(mapped.shading_normal(&hit).approx_eq(&normal, 1e-12));
+
+            let (mut plain_rng, mut mapped_rng) =
+                (SmallRng::seed_from_u64(52), SmallRng::seed_from_u64(52));
+            for _ in 0..100 {
+                let plain = base.scatter(&incoming, &hit, &mut plain_rng).unwrap();
+                let bent = mapped.scatter(&incoming, &hit, &mut mapped_rng).unwrap();
+                assert_eq!(plain.attenuation(), bent.attenuation());
+                assert!(
+                    plain
+                        .scattered()
+                        .direction()
+                        .approx_eq(&bent.scattered().direction(), 1e-12)
+                );
+                assert!(
+                    (base.scatter_pdf(&incoming, &hit, &plain.scattered())
+                        - mapped.scatter_pdf(&incoming, &hit, &plain.scattered()))
+                    .abs()
+                        < 1e-12
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_dispersive_wedge_bends_each_band_by_a_different_angle() {
+        let glass: Arc<dyn Material> = Arc::new(Dielectric::new_dispersive(
+            1.5,
+            0.05,
+            Colour::new(1.0, 1.0, 1.0),
+        ));
+        let mut rng = SmallRng::seed_from_u64(53);
+
+        // Refracts ray through a face with the given outward normal, retrying past reflections.
+        let mut refract = |ray: Ray, outward: Vec3| -> Ray {
+            let mut hit = HitRecord::new(ray.at(1.0), outward, 1.0, glass.clone());
+            hit.set_face_normal(&ray, outward);
+            let transmitted_side = -hit.normal();
+            loop {
+                let scattered = glass.scatter(&ray, &hit, &mut rng).unwrap().scattered();
+                if dot(scattered.direction(), transmitted_side) > 0.0 {
+                    return scattered;
+                }
+            }
+        };
+
+        // In through a face looking up +y at 40 degrees, out through one tilted 30 degrees.
+        let (sin, cos) = 40f64.to_radians().sin_cos();
+        let exit_normal = Vec3::new(0.0, 30f64.to_radians().cos(), 30f64.to_radians().sin());
+        let mut exit_direction = |wavelength: f64| {
+            let entering = ray(Point3::new(0.0, -cos, -sin), Vec3::new(0.0, cos, sin))
+                .with_wavelength(Some(wavelength));
+            let inside = refract(entering, Vec3::new(0.0, -1.0, 0.0));
+            unit_vector(refract(inside, exit_normal).direction())
+        };
+
+        let (violet, red) = (exit_direction(0.42), exit_direction(0.68));
+        let spread = f64::acos(dot(violet, red).clamp(-1.0, 1.0)).to_degrees();
+        assert!(spread > 1.0, "bands leave only {spread} degrees apart");
+    }
+
+    #[test]
+    fn a_height_ramp_tilts_the_normal_away_from_uphill() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let (tangent, bitangent) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut hit = HitRecord::with_uv(Point3::new(0.0, 0.0, 0.0), up, 1.0, grey(), 0.3, 0.4);
+        hit.set_tangent_frame(tangent, bitangent);
+
+        // Height rises by one per unit of v, so uphill is along the bitangent.
+        let ramp = Arc::new(GradientTexture::new(
+            Colour::new(0.0, 0.0, 0.0),
+            Colour::new(1.0, 1.0, 1.0),
+        ));
+        let bent = BumpMap::as_arc(grey(), ramp, 0.5).shading_normal(&hit);
+        assert!(bent.approx_eq(&unit_vector(up - 0.5 * bitangent), 1e-6));
+        assert!(dot(bent, bitangent) < 0.0);
+        assert!(dot(bent, tangent).abs() < 1e-9);
+
+        let flat = Arc::new(SolidColour::new(Colour::new(0.5, 0.5, 0.5)));
+        let unbent = BumpMap::as_arc(grey(), flat, 0.5).shading_normal(&hit);
+        assert!(unbent.approx_eq(&up, 1e-12));
+    }
+
+    #[test]
+    fn a_checker_fuzz_texture_gives_sharper_reflections_in_its_low_fuzz_cells() {
+        let fuzz = Arc::new(CheckerTexture::new_uv(
+            0.5,
+            Colour::new(0.0, 0.0, 0.0),
+            Colour::new(0.8, 0.8, 0.8),
+        ));
+        let albedo = Arc::new(SolidColour::new(Colour::new(0.9, 0.9, 0.9)));
+        let metal: Arc<dyn Material> = Arc::new(Metalic::with_fuzz_texture(albedo, fuzz));
+        let mirror_direction = unit_vector(Vec3::new(1.0, 1.0, 0.0));
+        let mut rng = SmallRng::seed_from_u64(81);
+
+        let mut spread_at = |u: f64, v: f64| {
+            let (ray, mut hit) = hit_at_45_degrees(metal.clone());
+            (hit.u, hit.v) = (u, v);
+            let deviations: Vec<f64> = (0..500)
+                .filter_map(|_| metal.scatter(&ray, &hit, &mut rng))
+                .map(|scatter| {
+                    (unit_vector(scatter.scattered().direction()) - mirror_direction)
+                        .length_squared()
+                })
+                .collect();
+            deviations.iter().sum::<f64>() / deviations.len() as f64
+        };
+
+        let (smooth, brushed) = (spread_at(0.25, 0.25), spread_at(0.75, 0.25));
+        assert!(smooth < 1e-12, "low fuzz cell spreads by {smooth}");
+        assert!(brushed > 0.05, "high fuzz cell spreads by only {brushed}");
+    }
+
+    #[test]
+    fn lambertian_from_colour_scatters_like_an_explicit_solid_colour() {
+        let colour = Colour::new(0.7, 0.2, 0.4);
+        let shorthand: Arc<dyn Material> = Arc::new(Lambertian::from_colour(colour));
+        let from_rgb: Arc<dyn Material> = Arc::new(Lambertian::from_rgb(0.7, 0.2, 0.4));
+        let explicit: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Arc::new(SolidColour::new(colour))));
+
+        let scatter_all = |material: &Arc<dyn Material>| {
+            let (ray, hit) = hit_at_45_degrees(material.clone());
+            let mut rng = SmallRng::seed_from_u64(82);
+            (0..50)
+                .map(|_| {
+                    let scatter = material.scatter(&ray, &hit, &mut rng).unwrap();
+                    (scatter.attenuation(), scatter.scattered().direction())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = scatter_all(&explicit);
+        assert!(
+            expected
+                .iter()
+                .all(|&(attenuation, _)| attenuation == colour)
+        );
+        assert_eq!(scatter_all(&shorthand), expected);
+        assert_eq!(scatter_all(&from_rgb), expected);
+    }
+
+    #[test]
+    fn a_mix_with_ratio_one_behaves_like_its_first_material() {
+        let matte = Lambertian::as_arc_from_colour(Colour::new(0.8, 0.3, 0.3));
+        let mirror = Metalic::as_arc(Colour::new(0.9, 0.9, 0.9), 0.0);
+        let mix: Arc<dyn Material> = MixMaterial::as_arc(matte.clone(), mirror, 1.0);
+        let mut rng = SmallRng::seed_from_u64(83);
+
+        // Mean attenuation and mean scattered direction over many samples.
+        let mut statistics = |material: Arc<dyn Material>| {
+            let (ray, hit) = hit_at_45_degrees(material.clone());
+            let samples = 20_000;
+            let (mut attenuation, mut direction) =
+                (Colour::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+            for _ in 0..samples {
+                let scatter = material.scatter(&ray, &hit, &mut rng).unwrap();
+                assert!(material.scatter_pdf(&ray, &hit, &scatter.scattered()) > 0.0);
+                attenuation += scatter.attenuation();
+                direction += unit_vector(scatter.scattered().direction());
+            }
+            (attenuation / samples as f64, direction / samples as f64)
+        };
+
+        let (mixed_attenuation, mixed_direction) = statistics(mix.clone());
+        let (matte_attenuation, matte_direction) = statistics(matte.clone());
+        assert_eq!(mixed_attenuation, matte_attenuation);
+        assert!(mixed_direction.approx_eq(&matte_direction, 0.02));
+
+        let (ray, hit) = hit_at_45_degrees(mix.clone());
+        let up = Ray::new(hit.hit_pos(), Vec3::new(0.2, 1.0, 0.1), 0.0);
+        assert_eq!(
+            mix.scatter_pdf(&ray, &hit, &up),
+            matte.scatter_pdf(&ray, &hit, &up)
+        );
+    }
+
+    #[test]
+    fn glass_seen_at_a_grazing_angle_takes_on_the_reflection_tint() {
+        let blue = Colour::new(0.1, 0.2, 0.9);
+        let glass: Arc<dyn Material> =
+            Arc::new(Dielectric::new(1.5, blue).with_reflection_tint(Colour::new(1.0, 1.0, 1.0)));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut rng = SmallRng::seed_from_u64(84);
+
+        // Mean red in the attenuation, only the reflection tint carries any.
+        let mut mean_red = |degrees_from_normal: f64| {
+            let (sin, cos) = degrees_from_normal.to_radians().sin_cos();
+            let incoming = ray(Point3::new(-sin, cos, 0.0), Vec3::new(sin, -cos, 0.0));
+            let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, glass.clone());
+            hit.set_face_normal(&incoming, up);
+            (0..5000)
+                .map(|_| {
+                    glass
+                        .scatter(&incoming, &hit, &mut rng)
+                        .unwrap()
+                        .attenuation()
+                        .r()
+                })
+                .sum::<f64>()
+                / 5000.0
+        };
+
+        let (head_on, grazing) = (mean_red(0.0), mean_red(89.0));
+        assert!(head_on < 0.15, "head on red {head_on}");
+        assert!(grazing > 0.8, "grazing red {grazing}");
+    }
+
+    #[test]
+    fn rough_metal_absorbs_some_grazing_reflections() {
+        let metal: Arc<dyn Material> = Metalic::as_arc(Colour::new(0.9, 0.9, 0.9), 1.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let (sin, cos) = 85f64.to_radians().sin_cos();
+        let incoming = ray(Point3::new(-sin, cos, 0.0), Vec3::new(sin, -cos, 0.0));
+        let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, metal.clone());
+        hit.set_face_normal(&incoming, up);
+        let mut rng = SmallRng::seed_from_u64(88);
+
+        let scatters: Vec<Option<ScatterRecord>> = (0..1000)
+            .map(|_| metal.scatter(&incoming, &hit, &mut rng))
+            .collect();
+        let absorbed = scatters.iter().filter(|scatter| scatter.is_none()).count();
+        assert!(absorbed > 100, "only {absorbed} of 1000 absorbed");
+        assert!(
+            scatters
+                .iter()
+                .flatten()
+                .all(|scatter| { dot(scatter.scattered().direction(), up) > 0.0 })
+        );
+    }
+
+    #[test]
+    fn a_spot_light_is_full_along_its_normal_and_dark_beyond_the_outer_cone() {
+        let spot = SpotLight::as_arc_from_colour(Colour::new(2.0, 2.0, 2.0), 20.0, 40.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        // Looking down at the light from a given angle off its normal.
+        let seen_from = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let incoming = ray(Point3::new(sin, cos, 0.0), Vec3::new(-sin, -cos, 0.0));
+            let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, spot.clone());
+            hit.set_face_normal(&incoming, up);
+            spot.emitted(&incoming, &hit)
+        };
+
+        assert_eq!(seen_from(0.0), Colour::new(2.0, 2.0, 2.0));
+        assert_eq!(seen_from(15.0), Colour::new(2.0, 2.0, 2.0));
+        let edge = seen_from(30.0).r();
+        assert!(
+            edge > 0.0 && edge < 2.0,
+            "halfway through the falloff {edge}"
+        );
+        for degrees in [41.0, 60.0, 89.0] {
+            assert_eq!(seen_from(degrees), Colour::new(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn a_clear_coat_reflects_more_at_grazing_angles() {
+        let plastic = Arc::new(CoatedDiffuse::new(
+            Arc::new(SolidColour::new(Colour::new(0.2, 0.4, 0.6))),
+            1.5,
+        ));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut rng = SmallRng::seed_from_u64(100);
+        // Share of scatters that bounce off the coat untinted, for light arriving the given
+        // angle off the normal.
+        let mut coat_fraction = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let incoming = ray(Point3::new(-sin, cos, 0.0), Vec3::new(sin, -cos, 0.0));
+            let mut hit = HitRecord::new(Point3::new(0.0, 0.0, 0.0), up, 1.0, plastic.clone());
+            hit.set_face_normal(&incoming, up);
+            let coat = (0..4000)
+                .filter_map(|_| plastic.scatter(&incoming, &hit, &mut rng))
+                .filter(|scatter| scatter.attenuation() == Colour::new(1.0, 1.0, 1.0))
+                .count();
+            coat as f64 / 4000.0
+        };
+
+        let head_on = coat_fraction(0.0);
+        let oblique = coat_fraction(60.0);
+        let grazing = coat_fraction(85.0);
+        // Schlick gives 4% head on for glass, 7% at 60 degrees and 65% at 85.
+        assert!((head_on - 0.04).abs() < 0.015, "head on {head_on}");
+        assert!(oblique > head_on && grazing > oblique);
+        assert!((grazing - Dielectric::reflectance(1.5, 85f64.to_radians().cos())).abs() < 0.04);
     }
 }
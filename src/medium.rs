@@ -0,0 +1,160 @@
+use crate::bounding::*;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::vec3::*;
+
+use core::f64;
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+use std::sync::Arc;
+
+// A convex boundary filled with fog of uniform density, like smoke or a cloud. Rays passing
+// through scatter at an exponentially distributed depth, and the phase function, usually a
+// HenyeyGreenstein, picks where they go from there.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+
+    pub fn boxed(
+        boundary: Box<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Box<ConstantMedium> {
+        Box::new(ConstantMedium::new(boundary, density, phase_function))
+    }
+}
+
+// hit has no rng to draw from, so the scattering depth is seeded from the ray itself. Every
+// bounce starts somewhere new, and a seeded render still comes out the same each time.
+fn free_path_sample(r: &Ray) -> f64 {
+    let (o, d) = (r.origin(), r.direction());
+    let seed = [
+        o.axis(0),
+        o.axis(1),
+        o.axis(2),
+        d.x(),
+        d.y(),
+        d.z(),
+        r.time(),
+    ]
+    .iter()
+    .fold(0u64, |h, c| h.rotate_left(13) ^ c.to_bits());
+    SmallRng::seed_from_u64(seed).random()
+}
+
+impl Hittable for ConstantMedium {
+    fn bounding_box(&self) -> &BoundingBox {
+        self.boundary.bounding_box()
+    }
+
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        // Where the whole line enters and leaves the boundary, so rays starting inside count.
+        let entry = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self.boundary.hit(r, entry.t + 0.0001, f64::INFINITY)?;
+
+        let t_enter = entry.t.max(ray_tmin).max(0.0);
+        let t_exit = exit.t.min(ray_tmax);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * f64::ln(1.0 - free_path_sample(r));
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // The normal means nothing inside fog, any direction facing the ray will do.
+        let normal = -unit_vector(r.direction());
+        let mut record = HitRecord::new(r.at(t), normal, t, self.phase_function.clone());
+        record.set_face_normal(r, normal);
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::testing::*;
+
+    #[test]
+    fn a_forward_henyey_greenstein_medium_scatters_rays_onwards() {
+        let mean_cos = |g: f64| {
+            let fog = ConstantMedium::new(
+                Box::new(unit_sphere()),
+                50.0,
+                Arc::new(HenyeyGreenstein::from_colour(Colour::new(0.9, 0.9, 0.9), g)),
+            );
+            let mut rng = SmallRng::seed_from_u64(97);
+            let forward = Vec3::new(0.0, 0.0, 1.0);
+            let mut total = 0.0;
+            for i in 0..2000 {
+                let start = Point3::new(0.0, 0.0, -3.0 - i as f64 * 1e-3);
+                let incoming = ray(start, forward);
+                let hit = fog.hit(&incoming, 0.001, f64::INFINITY).unwrap();
+                let scattered = hit
+                    .material_ref()
+                    .scatter(&incoming, &hit, &mut rng)
+                    .unwrap();
+                total += dot(unit_vector(scattered.scattered().direction()), forward);
+            }
+            total / 2000.0
+        };
+
+        // The mean cosine of Henyey-Greenstein is g itself.
+        let onwards = mean_cos(0.7);
+        assert!((onwards - 0.7).abs() < 0.05, "mean cosine {onwards}");
+        assert!(mean_cos(0.0).abs() < 0.05);
+        assert!(mean_cos(-0.7) < -0.6);
+    }
+
+    #[test]
+    fn thin_fog_lets_most_rays_through_and_thick_fog_stops_them_near_the_edge() {
+        let fog = |density: f64| {
+            ConstantMedium::new(
+                Box::new(unit_sphere()),
+                density,
+                Arc::new(HenyeyGreenstein::from_colour(
+                    Colour::new(0.9, 0.9, 0.9),
+                    0.0,
+                )),
+            )
+        };
+        let shots = |medium: &ConstantMedium| {
+            (0..1000)
+                .filter_map(|i| {
+                    let start = Point3::new(0.0, 0.0, -3.0 - i as f64 * 1e-3);
+                    medium.hit(&ray(start, Vec3::new(0.0, 0.0, 1.0)), 0.001, f64::INFINITY)
+                })
+                .map(|hit| hit.hit_pos().axis(2))
+                .collect::<Vec<_>>()
+        };
+
+        // Two units of fog at density 0.1 stop about 18% of rays.
+        let thin = shots(&fog(0.1)).len();
+        assert!((120..250).contains(&thin), "{thin} rays stopped");
+
+        let thick = shots(&fog(100.0));
+        assert_eq!(thick.len(), 1000);
+        assert!(thick.iter().all(|&z| (-1.0..-0.9).contains(&z)));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::bvh::BvhTree;
+use crate::material::Material;
+use crate::triangle::*;
+use crate::vec3::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+pub fn load_obj<P>(path: P, mat: Arc<dyn Material>) -> io::Result<BvhTree>
+where
+    P: AsRef<Path>,
+{
+    let source = fs::read_to_string(path)?;
+    parse_obj(&source, mat)
+}
+
+// Only geometry is read; groups, smoothing groups and material libraries are skipped. Faces
+// with a normal on every vertex are smooth shaded. The triangles come back in their own built
+// BvhTree, which can be added to a scene as a single object.
+pub fn parse_obj(source: &str, mat: Arc<dyn Material>) -> io::Result<BvhTree> {
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+
+    let mut triangles = BvhTree::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                let v = parse_floats(&mut tokens, 3, line_number)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            Some("vt") => {
+                let vt = parse_floats(&mut tokens, 2, line_number)?;
+                uvs.push((vt[0], vt[1]));
+            }
+            Some("vn") => {
+                let vn = parse_floats(&mut tokens, 3, line_number)?;
+                normals.push(Vec3::new(vn[0], vn[1], vn[2]));
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|token| {
+                        parse_face_vertex(
+                            token,
+                            positions.len(),
+                            uvs.len(),
+                            normals.len(),
+                            line_number,
+                        )
+                    })
+                    .collect::<io::Result<Vec<FaceVertex>>>()?;
+
+                if face.len() < 3 {
+                    return Err(invalid_data(line_number, "face has fewer than 3 vertices"));
+                }
+
+                // Polygons are split into a fan of triangles around the first vertex.
+                for i in 1..face.len() - 1 {
+                    let corners = [&face[0], &face[i], &face[i + 1]];
+                    let (a, b, c) = (
+                        positions[corners[0].position],
+                        positions[corners[1].position],
+                        positions[corners[2].position],
+                    );
+
+                    let mut triangle = match (corners[0].uv, corners[1].uv, corners[2].uv) {
+                        (Some(uv_a), Some(uv_b), Some(uv_c)) => Triangle::with_uvs(
+                            a,
+                            b,
+                            c,
+                            [uvs[uv_a], uvs[uv_b], uvs[uv_c]],
+                            mat.clone(),
+                        ),
+                        _ => Triangle::new(a, b, c, mat.clone()),
+                    };
+                    if let (Some(n_a), Some(n_b), Some(n_c)) =
+                        (corners[0].normal, corners[1].normal, corners[2].normal)
+                    {
+                        triangle.set_vertex_normals([normals[n_a], normals[n_b], normals[n_c]]);
+                    }
+                    triangles.add(Box::new(triangle));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    triangles.build();
+    Ok(triangles)
+}
+
+fn invalid_data(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("OBJ line {}: {}", line_number, message),
+    )
+}
+
+fn parse_floats(
+    tokens: &mut SplitWhitespace,
+    count: usize,
+    line_number: usize,
+) -> io::Result<Vec<f64>> {
+    let values = tokens
+        .take(count)
+        .map(|token| {
+            token
+                .parse::<f64>()
+                .map_err(|_| invalid_data(line_number, &format!("invalid number `{}`", token)))
+        })
+        .collect::<io::Result<Vec<f64>>>()?;
+
+    if values.len() < count {
+        return Err(invalid_data(
+            line_number,
+            &format!("expected {} values, found {}", count, values.len()),
+        ));
+    }
+
+    Ok(values)
+}
+
+// Face vertices are `v`, `v/vt`, `v//vn` or `v/vt/vn`, using 1-based or negative (relative) indices.
+fn parse_face_vertex(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+    line_number: usize,
+) -> io::Result<FaceVertex> {
+    let mut parts = token.split('/');
+
+    let position = match parts.next() {
+        Some(index) => resolve_index(index, position_count, line_number)?,
+        None => return Err(invalid_data(line_number, "empty face vertex")),
+    };
+
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, uv_count, line_number)?),
+    };
+
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, normal_count, line_number)?),
+    };
+
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+fn resolve_index(token: &str, count: usize, line_number: usize) -> io::Result<usize> {
+    let index = token
+        .parse::<i64>()
+        .map_err(|_| invalid_data(line_number, &format!("invalid index `{}`", token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(invalid_data(
+            line_number,
+            &format!("index {} out of range", index),
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::testing::*;
+
+    // A unit cube around the origin, six quads that fan into twelve triangles.
+    const CUBE: &str = "\
+# cube
+v -1 -1 -1
+v  1 -1 -1
+v  1  1 -1
+v -1  1 -1
+v -1 -1  1
+v  1 -1  1
+v  1  1  1
+v -1  1  1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+    #[test]
+    fn a_cube_loads_as_twelve_triangles_inside_its_bounds() {
+        let cube = parse_obj(CUBE, grey()).unwrap();
+
+        assert_eq!(cube.object_count(), 12);
+        // Each face's box is padded on its flat axis, which reaches just past the cube.
+        let corner = Vec3::new(1.0, 1.0, 1.0);
+        assert!(Vec3::from(cube.bounds().lower()).approx_eq(&-corner, 1e-4));
+        assert!(Vec3::from(cube.bounds().upper()).approx_eq(&corner, 1e-4));
+
+        let towards = ray(Point3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cube.hit(&towards, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn malformed_lines_report_their_line_number() {
+        let error = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", grey())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 3"));
+
+        let error = parse_obj("v 0 zero 0\n", grey()).err().unwrap();
+        assert!(error.to_string().contains("line 1"));
+    }
+}
@@ -4,13 +4,23 @@ use crate::material::Material;
 use crate::ray::*;
 use crate::vec3::*;
 
+use rand::{Rng, RngCore};
 use std::ops::Range;
 use std::sync::Arc;
 
-pub struct Quad<F>
-where
-    F: Fn(String),
-{
+// Decides which (alpha, beta) points of the parallelogram belong to a quad.
+pub type InteriorFn = Box<dyn Fn(f64, f64) -> bool + Send + Sync>;
+
+// Side length of the grid of (alpha, beta) points used to estimate how much of the
+// parallelogram an interior predicate keeps. The estimate is only as fine as the grid, so
+// thin or small cut outs get an approximate area, and one that falls between the grid points
+// gets none at all.
+const COVERAGE_SAMPLES: u32 = 32;
+
+// Tries at finding a point inside a cut out quad before giving up on the sample.
+const MAX_SAMPLE_TRIES: u32 = 64;
+
+pub struct Quad {
     q: Point3,
     u: Vec3,
     v: Vec3,
@@ -20,12 +30,58 @@ where
     normal: Vec3,
     d: f64,
     w: Vec3,
+    area: f64,
 
-    f: F,
+    // None keeps the whole parallelogram.
+    interior: Option<InteriorFn>,
 }
 
-impl<F: Fn(String)> Quad<F> {
-    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>, f: F) -> Quad<F> {
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Quad {
+        Quad::build(q, u, v, mat, None)
+    }
+
+    pub fn boxed(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Box<Quad> {
+        Box::new(Quad::new(q, u, v, mat))
+    }
+
+    // Only keeps the parts of the parallelogram where interior(alpha, beta) holds, with alpha
+    // and beta the hit's coordinates along u and v in [0, 1). This gives discs, rings,
+    // triangles and the like. The area used when sampling it as a light is estimated on a
+    // grid, see COVERAGE_SAMPLES.
+    pub fn with_interior<F>(
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        mat: Arc<dyn Material>,
+        interior: F,
+    ) -> Quad
+    where
+        F: Fn(f64, f64) -> bool + Send + Sync + 'static,
+    {
+        Quad::build(q, u, v, mat, Some(Box::new(interior)))
+    }
+
+    pub fn boxed_with_interior<F>(
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        mat: Arc<dyn Material>,
+        interior: F,
+    ) -> Box<Quad>
+    where
+        F: Fn(f64, f64) -> bool + Send + Sync + 'static,
+    {
+        Box::new(Quad::with_interior(q, u, v, mat, interior))
+    }
+
+    fn build(
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        mat: Arc<dyn Material>,
+        interior: Option<InteriorFn>,
+    ) -> Quad {
         let bound1 = BoundingBox::new(q, q + u + v);
         let bound2 = BoundingBox::new(q + u, q + v);
 
@@ -37,6 +93,20 @@ impl<F: Fn(String)> Quad<F> {
         let d = dot(normal, q.into());
 
         let w = n / dot(n, n);
+
+        let coverage = interior.as_ref().map_or(1.0, |interior| {
+            let step = 1.0 / COVERAGE_SAMPLES as f64;
+            let kept = (0..COVERAGE_SAMPLES * COVERAGE_SAMPLES)
+                .filter(|i| {
+                    let alpha = ((i % COVERAGE_SAMPLES) as f64 + 0.5) * step;
+                    let beta = ((i / COVERAGE_SAMPLES) as f64 + 0.5) * step;
+                    interior(alpha, beta)
+                })
+                .count();
+            kept as f64 * step * step
+        });
+        let area = n.length() * coverage;
+
         Quad {
             q,
             u,
@@ -46,26 +116,29 @@ impl<F: Fn(String)> Quad<F> {
             normal,
             d,
             w,
-            f,
+            area,
+            interior,
         }
     }
 
-    pub fn boxed(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>, f: F) -> Box<Quad<F>> {
-        Box::new(Quad::new(q, u, v, mat, f))
-    }
-
     pub fn is_interior(&self, a: &f64, b: &f64) -> Option<(f64, f64)> {
         let range: Range<f64> = 0.0..1.0;
 
-        if !range.contains(a) || !range.contains(b) {
+        if !range.contains(a) || !range.contains(b) || !self.keeps(*a, *b) {
             None
         } else {
             Some((*a, *b))
         }
     }
+
+    fn keeps(&self, alpha: f64, beta: f64) -> bool {
+        self.interior
+            .as_ref()
+            .is_none_or(|interior| interior(alpha, beta))
+    }
 }
 
-impl<F: Fn(String) + Send + Sync> Hittable for Quad<F> {
+impl Hittable for Quad {
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
         let denom = dot(self.normal, r.direction());
 
@@ -85,11 +158,11 @@ impl<F: Fn(String) + Send + Sync> Hittable for Quad<F> {
         let beta = dot(self.w, cross(self.u, planar_hit_vec.into()));
 
         if let Some((u, v)) = self.is_interior(&alpha, &beta) {
-            // (self.f)(format_args!("Intersection with Quad at: {:?}", intersection).to_string());
-            let mut record = HitRecord::new(intersection, self.normal, t, self.mat.clone(), u, v);
+            let mut record =
+                HitRecord::with_uv(intersection, self.normal, t, self.mat.clone(), u, v);
             record.set_face_normal(r, self.normal);
+            record.set_tangent_frame(unit_vector(self.u), unit_vector(self.v));
 
-            // (self.f)(format_args!("Face normal: {:?}", record.normal()).to_string());
             Some(record)
         } else {
             None
@@ -99,6 +172,37 @@ impl<F: Fn(String) + Send + Sync> Hittable for Quad<F> {
     fn bounding_box(&self) -> &BoundingBox {
         &self.bounds
     }
+
+    // Converts the uniform area density 1/A into a solid angle density from origin.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let Some(record) = self.hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY)
+        else {
+            return 0.0;
+        };
+
+        let distance_squared = record.t * record.t * direction.length_squared();
+        let cosine = f64::abs(dot(*direction, record.normal()) / direction.length());
+        if cosine < 1e-8 || self.area <= 0.0 {
+            return 0.0;
+        }
+
+        distance_squared / (cosine * self.area)
+    }
+
+    // Rejection samples the parallelogram, so the density matches the estimated area. When
+    // every try misses the cut out, the direction runs along the plane instead, which never
+    // hits the quad and so gets a pdf_value of zero.
+    fn random(&self, origin: &Point3, rng: &mut dyn RngCore) -> Vec3 {
+        for _ in 0..MAX_SAMPLE_TRIES {
+            let (alpha, beta) = (rng.random::<f64>(), rng.random::<f64>());
+            if self.keeps(alpha, beta) {
+                let p = self.q + (alpha * self.u) + (beta * self.v);
+                return Vec3::from(p - *origin);
+            }
+        }
+
+        self.u
+    }
 }
 
 pub struct Cube {
@@ -121,42 +225,36 @@ impl Cube {
             dx,
             dy,
             mat.clone(),
-            |_| {},
         ));
         sides.add(Quad::boxed(
             Point3::new(max.axis(0), min.axis(1), max.axis(2)),
             -dz,
             dy,
             mat.clone(),
-            |_| {},
         ));
         sides.add(Quad::boxed(
             Point3::new(max.axis(0), min.axis(1), min.axis(2)),
             -dx,
             dy,
             mat.clone(),
-            |_| {},
         ));
         sides.add(Quad::boxed(
             Point3::new(min.axis(0), min.axis(1), min.axis(2)),
             dz,
             dy,
             mat.clone(),
-            |_| {},
         ));
         sides.add(Quad::boxed(
             Point3::new(min.axis(0), max.axis(1), max.axis(2)),
             dx,
             -dz,
             mat.clone(),
-            |_| {},
         ));
         sides.add(Quad::boxed(
             Point3::new(min.axis(0), min.axis(1), min.axis(2)),
             dx,
             dz,
             mat.clone(),
-            |_| {},
         ));
 
         Cube { sides }
@@ -176,3 +274,146 @@ impl Hittable for Cube {
         self.sides.bounding_box()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn tangent_runs_along_the_u_edge() {
+        let grey = grey();
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            grey,
+        );
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = quad.hit(&ray, 0.001, f64::INFINITY).unwrap();
+
+        let (tangent, bitangent) = hit.tangent_frame();
+        assert!((dot(tangent, Vec3::new(1.0, 0.0, 0.0)) - 1.0).abs() < 1e-9);
+        assert!((dot(bitangent, Vec3::new(0.0, 1.0, 0.0)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn random_points_stay_inside_the_parametric_bounds() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+
+        let grey = grey();
+        let square = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            grey.clone(),
+        );
+        // Only the disc inscribed in the unit square of alpha and beta.
+        let disc = Quad::with_interior(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey,
+            |alpha, beta| (alpha - 0.5).powi(2) + (beta - 0.5).powi(2) <= 0.25,
+        );
+
+        let origin = Point3::new(0.5, 0.5, 1.0);
+        let mut rng = SmallRng::seed_from_u64(99);
+        for _ in 0..1000 {
+            let p = origin + square.random(&origin, &mut rng);
+            assert!((-1.0..=1.0).contains(&p.axis(0)) && (-1.0..=2.0).contains(&p.axis(1)));
+            assert!((p.axis(2) + 2.0).abs() < 1e-9);
+
+            let p = origin + disc.random(&origin, &mut rng);
+            assert!(p.axis(0).powi(2) + p.axis(1).powi(2) <= 1.0 + 1e-9);
+        }
+    }
+
+    #[test]
+    fn a_triangular_cutout_rejects_hits_in_the_opposite_corner() {
+        // The half of a 2x2 square at z = -2 nearest its corner at (-1, -1).
+        let triangle = Quad::with_interior(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+            |alpha, beta| alpha + beta <= 1.0,
+        );
+        let towards = |x: f64, y: f64| ray(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(
+            triangle
+                .hit(&towards(-0.8, -0.8), 0.001, f64::INFINITY)
+                .is_some()
+        );
+        assert!(
+            triangle
+                .hit(&towards(0.8, 0.8), 0.001, f64::INFINITY)
+                .is_none()
+        );
+        assert!(
+            triangle
+                .hit(&towards(0.8, -0.9), 0.001, f64::INFINITY)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn many_quads_share_one_hittable_list() {
+        use crate::hittable::HittableList;
+
+        // A row of unit squares along x, each a step further back than the last.
+        let mut list = HittableList::new();
+        for k in 0..50 {
+            list.add(Quad::boxed(
+                Point3::new(k as f64, 0.0, -1.0 - k as f64),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                grey(),
+            ));
+        }
+        list.add(Quad::boxed_with_interior(
+            Point3::new(-1.0, 0.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            grey(),
+            |alpha, _| alpha < 0.5,
+        ));
+        assert_eq!(list.len(), 51);
+
+        for k in 0..50 {
+            let down = ray(
+                Point3::new(k as f64 + 0.5, 0.5, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+            );
+            let hit = list.hit(&down, 0.001, f64::INFINITY).unwrap();
+            assert!((hit.t - (1.0 + k as f64)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn a_cut_out_that_sampling_cannot_find_gives_a_zero_density_direction() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+
+        // A sliver far thinner than any try could land in, and than the area grid can see.
+        let sliver = Quad::with_interior(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+            |alpha, _| alpha < 1e-12,
+        );
+
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let mut rng = SmallRng::seed_from_u64(99);
+        for _ in 0..100 {
+            let direction = sliver.random(&origin, &mut rng);
+            assert_eq!(sliver.pdf_value(&origin, &direction), 0.0);
+        }
+        // Straight at the sliver's edge, the zero area can't blow the density up either.
+        let at_edge = Vec3::new(-1.0, 0.0, -2.0);
+        assert_eq!(sliver.pdf_value(&origin, &at_edge), 0.0);
+    }
+}
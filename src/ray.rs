@@ -1,10 +1,12 @@
 use crate::vec3::*;
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
     time: f64,
+    // In micrometres, set once a dispersive material has picked a band for this path.
+    wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -13,9 +15,18 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
         }
     }
 
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray { wavelength, ..self }
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn direction(&self) -> Vec3 {
         self.direction
     }
@@ -25,7 +36,7 @@ impl Ray {
     }
 
     pub fn at(&self, t: f64) -> Point3 {
-        Point3::from(self.origin() + self.direction * t)
+        self.origin() + self.direction * t
     }
 
     pub fn time(&self) -> f64 {
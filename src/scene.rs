@@ -0,0 +1,308 @@
+use crate::bvh::BvhTree;
+use crate::camera::Camera;
+use crate::material::*;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::texture::*;
+use crate::triangle::Triangle;
+use crate::vec3::*;
+use crate::world::Scene;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub textures: BTreeMap<String, TextureDescription>,
+    pub materials: BTreeMap<String, MaterialDescription>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub output: String,
+    pub aspect_ratio: f64,
+    pub image_width: u64,
+    pub vfov: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub up: Vec3,
+    pub focus_distance: f64,
+    #[serde(default)]
+    pub focus_angle: f64,
+    #[serde(default)]
+    pub samples_per_pixel: Option<i32>,
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    #[serde(default)]
+    pub background: Option<Colour>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDescription {
+    Solid {
+        colour: Colour,
+    },
+    Checker {
+        scale: f64,
+        even: Colour,
+        odd: Colour,
+    },
+    Image {
+        path: String,
+    },
+    Noise {
+        scale: f64,
+    },
+}
+
+// Textures are referred to by name from the scene's texture table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDescription {
+    Lambertian {
+        texture: String,
+    },
+    Metal {
+        albedo: Colour,
+        fuzz: f64,
+    },
+    Dielectric {
+        refractive_index: f64,
+        #[serde(default = "white")]
+        albedo: Colour,
+    },
+    DiffuseLight {
+        texture: String,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+// Materials are referred to by name from the scene's material table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectDescription {
+    Sphere {
+        center: Point3,
+        radius: f64,
+        material: String,
+    },
+    Quad {
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    Triangle {
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        material: String,
+    },
+}
+
+fn white() -> Colour {
+    Colour::new(1.0, 1.0, 1.0)
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+pub fn load_scene<P>(path: P) -> io::Result<(Camera, Scene)>
+where
+    P: AsRef<Path>,
+{
+    let source = fs::read_to_string(path)?;
+    let description: SceneDescription = serde_json::from_str(&source).map_err(io::Error::from)?;
+    build_scene(&description)
+}
+
+// Builds every texture and material up front, so a bad name fails the whole scene rather
+// than just the objects that use it.
+pub fn build_scene(description: &SceneDescription) -> io::Result<(Camera, Scene)> {
+    let mut textures: BTreeMap<&str, Arc<dyn Texture>> = BTreeMap::new();
+    for (name, texture) in &description.textures {
+        textures.insert(name, build_texture(texture)?);
+    }
+
+    let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+    for (name, material) in &description.materials {
+        materials.insert(name, build_material(material, &textures)?);
+    }
+
+    let material = |name: &str| -> io::Result<Arc<dyn Material>> {
+        materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| unknown_name("material", name))
+    };
+
+    let mut world = BvhTree::new();
+    for object in &description.objects {
+        match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material: name,
+            } => world.add(Box::new(Sphere::new(
+                Ray::new(*center, Vec3::default(), 0.0),
+                *radius,
+                material(name)?,
+            ))),
+            ObjectDescription::Quad {
+                q,
+                u,
+                v,
+                material: name,
+            } => world.add(Quad::boxed(*q, *u, *v, material(name)?)),
+            ObjectDescription::Triangle {
+                a,
+                b,
+                c,
+                material: name,
+            } => world.add(Triangle::boxed(*a, *b, *c, material(name)?)),
+        }
+    }
+    world.build();
+
+    let camera = build_camera(&description.camera)?;
+
+    Ok((camera, Scene::new(world)))
+}
+
+fn build_camera(description: &CameraDescription) -> io::Result<Camera> {
+    let mut builder = Camera::builder()
+        .aspect_ratio(description.aspect_ratio)
+        .image_width(description.image_width)
+        .vfov(description.vfov)
+        .look_from(description.look_from)
+        .look_at(description.look_at)
+        .up(description.up)
+        .focus_distance(description.focus_distance)
+        .focus_angle(description.focus_angle)
+        .output(&description.output);
+
+    if let Some(samples) = description.samples_per_pixel {
+        builder = builder.samples_per_pixel(samples);
+    }
+    if let Some(depth) = description.max_depth {
+        builder = builder.max_depth(depth);
+    }
+
+    let mut camera = builder.build()?;
+    if let Some(background) = description.background {
+        camera.set_background(background);
+    }
+
+    Ok(camera)
+}
+
+fn build_texture(description: &TextureDescription) -> io::Result<Arc<dyn Texture>> {
+    let texture: Arc<dyn Texture> = match description {
+        TextureDescription::Solid { colour } => SolidColour::as_arc(*colour),
+        TextureDescription::Checker { scale, even, odd } => {
+            Arc::new(CheckerTexture::new_with_colours(*scale, *even, *odd))
+        }
+        TextureDescription::Image { path } => {
+            // ImageTexture panics on a missing file, so catch the common case here.
+            if !Path::new(path).exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("texture image {} not found", path),
+                ));
+            }
+            Arc::new(ImageTexture::new(path))
+        }
+        TextureDescription::Noise { scale } => Arc::new(NoiseTexture::new_with_scale(*scale)),
+    };
+
+    Ok(texture)
+}
+
+fn build_material(
+    description: &MaterialDescription,
+    textures: &BTreeMap<&str, Arc<dyn Texture>>,
+) -> io::Result<Arc<dyn Material>> {
+    let texture = |name: &str| -> io::Result<Arc<dyn Texture>> {
+        textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| unknown_name("texture", name))
+    };
+
+    let material: Arc<dyn Material> = match description {
+        MaterialDescription::Lambertian { texture: name } => Lambertian::as_arc(texture(name)?),
+        MaterialDescription::Metal { albedo, fuzz } => Metalic::as_arc(*albedo, *fuzz),
+        MaterialDescription::Dielectric {
+            refractive_index,
+            albedo,
+        } => Dielectric::as_arc(*refractive_index, *albedo),
+        MaterialDescription::DiffuseLight {
+            texture: name,
+            intensity,
+        } => Arc::new(DiffuseLight::with_intensity(texture(name)?, *intensity)),
+    };
+
+    Ok(material)
+}
+
+fn unknown_name(kind: &str, name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("scene refers to unknown {} {}", kind, name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+
+    const SCENE: &str = r#"{
+        "camera": {
+            "output": "unused.ppm",
+            "aspect_ratio": 1.0,
+            "image_width": 8,
+            "vfov": 40.0,
+            "look_from": [0.0, 0.0, 0.0],
+            "look_at": [0.0, 0.0, -1.0],
+            "up": [0.0, 1.0, 0.0],
+            "focus_distance": 1.0,
+            "background": [0.5, 0.7, 1.0]
+        },
+        "textures": { "red": { "type": "solid", "colour": [0.8, 0.1, 0.1] } },
+        "materials": { "matte": { "type": "lambertian", "texture": "red" } },
+        "objects": [
+            { "type": "sphere", "center": [0.0, 0.0, -5.0], "radius": 1.0, "material": "matte" }
+        ]
+    }"#;
+
+    #[test]
+    fn scene_round_trips_through_json_and_builds_a_hittable_sphere() {
+        let description: SceneDescription = serde_json::from_str(SCENE).unwrap();
+        let json = serde_json::to_string(&description).unwrap();
+        let reloaded: SceneDescription = serde_json::from_str(&json).unwrap();
+
+        let ObjectDescription::Sphere { center, .. } = &reloaded.objects[0] else {
+            panic!("expected the sphere back");
+        };
+        assert_eq!(center.axis(2), -5.0);
+        assert_eq!(reloaded.camera.background, Some(Colour::new(0.5, 0.7, 1.0)));
+
+        let (_, scene) = build_scene(&reloaded).unwrap();
+        let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+    }
+}
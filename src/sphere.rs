@@ -5,11 +5,13 @@ use crate::ray::*;
 use crate::vec3::*;
 
 use core::f64;
+use rand::{Rng, RngCore};
 use std::sync::Arc;
 
 pub struct Sphere {
     movement: Ray,
     radius: f64,
+    radius_squared: f64,
     mat: Arc<dyn Material>,
     bounding: BoundingBox,
 }
@@ -18,6 +20,8 @@ impl Sphere {
     pub fn new(movement: Ray, radius: f64, mat: Arc<dyn Material>) -> Sphere {
         let rvec = Vec3::new(radius, radius, radius);
 
+        // Enclose the sphere at both ends of its path so the box covers the whole sweep. Ray
+        // times stay within 0..1, Camera::set_shutter clamps to that.
         let box1 = BoundingBox::new(movement.at(0.0) - rvec, movement.at(0.0) + rvec);
         let box2 = BoundingBox::new(movement.at(1.0) - rvec, movement.at(1.0) + rvec);
 
@@ -26,11 +30,16 @@ impl Sphere {
         Sphere {
             movement,
             radius,
+            radius_squared: radius * radius,
             mat,
             bounding: movement_bounds,
         }
     }
 
+    pub fn center_at(&self, time: f64) -> Point3 {
+        self.movement.at(time)
+    }
+
     pub fn get_sphere_uv(&self, p: &Point3) -> (f64, f64) {
         let theta = f64::acos(-p.axis(1));
         let phi = f64::atan2(-p.axis(2), p.axis(0)) + f64::consts::PI;
@@ -45,11 +54,13 @@ impl Hittable for Sphere {
     }
 
     fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
-        let current_position = self.movement.at(ray.time());
+        let current_position = self.center_at(ray.time());
         let oc: Vec3 = (current_position - ray.origin()).into();
+        // Directions don't need to be unit length, a scales the roots so t stays in the ray's
+        // own parameterisation.
         let a = ray.direction().length_squared();
         let h = dot(ray.direction(), oc);
-        let c = oc.length_squared() - self.radius * self.radius;
+        let c = oc.length_squared() - self.radius_squared;
 
         let discriminant = (h * h) - (a * c);
 
@@ -73,9 +84,119 @@ impl Hittable for Sphere {
 
         let (u, v) = self.get_sphere_uv(&normal);
 
-        let mut hit_record = HitRecord::new(p, normal.into(), root, self.mat.clone(), u, v);
+        let mut hit_record = HitRecord::with_uv(p, normal.into(), root, self.mat.clone(), u, v);
         hit_record.set_face_normal(ray, normal.into());
 
+        // dP/du runs around the y axis, dP/dv towards +y. Both vanish at the poles, which
+        // are left without a tangent frame.
+        let outward = Vec3::from(normal);
+        let around = Vec3::new(outward.z(), 0.0, -outward.x());
+        if !around.near_zero() {
+            let tangent = unit_vector(around);
+            hit_record.set_tangent_frame(tangent, cross(outward, tangent));
+        }
+
         Some(hit_record)
     }
+
+    // Samples the cone of directions the sphere subtends, which only holds for stationary
+    // spheres, so a moving one is treated as sitting at its starting position.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self
+            .hit(&Ray::new(*origin, *direction, 0.0), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = Vec3::from(self.center_at(0.0) - *origin).length_squared();
+        if distance_squared <= self.radius_squared {
+            return 0.0;
+        }
+
+        let cos_theta_max = f64::sqrt(1.0 - self.radius_squared / distance_squared);
+        let solid_angle = 2.0 * f64::consts::PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut dyn RngCore) -> Vec3 {
+        let direction = Vec3::from(self.center_at(0.0) - *origin);
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius_squared {
+            return direction;
+        }
+
+        let r1 = rng.random::<f64>();
+        let r2 = rng.random::<f64>();
+
+        let cos_theta_max = f64::sqrt(1.0 - self.radius_squared / distance_squared);
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * f64::consts::PI * r1;
+        let sin_theta = f64::sqrt(1.0 - z * z);
+
+        // Build a basis around the direction to the centre and place the sample in it.
+        let w = unit_vector(direction);
+        let (u, v) = orthonormal_basis(w);
+
+        (f64::cos(phi) * sin_theta * u) + (f64::sin(phi) * sin_theta * v) + (z * w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn uv_puts_the_north_pole_at_v_one_and_plus_x_at_u_half() {
+        let sphere = unit_sphere();
+
+        let down = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let pole = sphere.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((pole.v - 1.0).abs() < 1e-9);
+
+        let towards_x = Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let side = sphere.hit(&towards_x, 0.001, f64::INFINITY).unwrap();
+        assert!((side.u - 0.5).abs() < 1e-9);
+        assert!((side.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bounding_box_is_the_cube_around_the_sphere() {
+        let sphere = Sphere::new(still(Point3::new(0.0, 0.0, 0.0)), 0.5, grey());
+
+        // Half a unit on every axis is far wider than the padding given to flat boxes.
+        let bounds = sphere.bounding_box();
+        assert_eq!(bounds.lower(), Point3::new(-0.5, -0.5, -0.5));
+        assert_eq!(bounds.upper(), Point3::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn bounding_box_of_a_moving_sphere_covers_both_ends() {
+        let moving = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let sphere = Sphere::new(moving, 0.5, grey());
+
+        let bounds = sphere.bounding_box();
+        assert_eq!(bounds.lower(), Point3::new(-0.5, -0.5, -0.5));
+        assert_eq!(bounds.upper(), Point3::new(2.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn an_unnormalised_direction_hits_the_same_point_at_a_third_of_t() {
+        let sphere = unit_sphere();
+        let origin = Point3::new(0.3, 0.2, 4.0);
+        let unit = unit_vector(Vec3::new(-0.1, 0.0, -1.0));
+
+        let normalised = sphere
+            .hit(&ray(origin, unit), 0.001, f64::INFINITY)
+            .unwrap();
+        let tripled = sphere
+            .hit(&ray(origin, 3.0 * unit), 0.001, f64::INFINITY)
+            .unwrap();
+
+        assert!((tripled.t - normalised.t / 3.0).abs() < 1e-12);
+        assert!(Vec3::from(tripled.hit_pos() - normalised.hit_pos()).near_zero());
+        assert!(tripled.normal().approx_eq(&normalised.normal(), 1e-12));
+    }
 }
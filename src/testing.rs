@@ -0,0 +1,62 @@
+// Fixtures shared by the unit tests in every module.
+use crate::bvh::BvhTree;
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::hittable::Hittable;
+use crate::material::{Lambertian, Material};
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::vec3::*;
+use crate::world::Scene;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub fn grey() -> Arc<dyn Material> {
+    Lambertian::as_arc_from_colour(Colour::new(0.5, 0.5, 0.5))
+}
+
+// A ray at time 0.
+pub fn ray(origin: Point3, direction: Vec3) -> Ray {
+    Ray::new(origin, direction, 0.0)
+}
+
+// The centre path of a sphere that doesn't move.
+pub fn still(center: Point3) -> Ray {
+    Ray::new(center, Vec3::new(0.0, 0.0, 0.0), 0.0)
+}
+
+pub fn sphere(center: Point3, radius: f64, material: Arc<dyn Material>) -> Box<Sphere> {
+    Box::new(Sphere::new(still(center), radius, material))
+}
+
+pub fn unit_sphere() -> Sphere {
+    Sphere::new(still(Point3::new(0.0, 0.0, 0.0)), 1.0, grey())
+}
+
+pub fn scene_of(objects: Vec<Box<dyn Hittable>>) -> Scene {
+    Scene::new(BvhTree::from_objects(objects))
+}
+
+// Small, seeded and quiet, looking down -z from the origin.
+pub fn test_camera(width: u64, height: u64, samples: i32) -> Camera {
+    let mut camera = Camera::builder()
+        .dimensions(width, height)
+        .look_from(Point3::new(0.0, 0.0, 0.0))
+        .look_at(Point3::new(0.0, 0.0, -1.0))
+        .samples_per_pixel(samples)
+        .build()
+        .unwrap();
+    camera.set_seed(7);
+    camera.set_progress_callback(|_, _| {});
+    camera
+}
+
+pub fn channels(image: &Framebuffer) -> Vec<[f64; 3]> {
+    image.pixels.iter().map(|p| [p.r(), p.g(), p.b()]).collect()
+}
+
+// A path in the temp directory unique to this test process.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tracer-test-{}-{}", std::process::id(), name))
+}
@@ -1,9 +1,11 @@
+use core::f64;
+use std::io;
 use std::path::Path;
 use std::sync::Arc;
 
 use crate::vec3::*;
-use image::{open, ImageBuffer, RgbImage};
-use noise::{NoiseFn, Perlin, Turbulence};
+use image::{Rgb32FImage, RgbImage, open};
+use noise::{NoiseFn, Perlin, Seedable, Turbulence};
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point3) -> Colour;
@@ -30,8 +32,51 @@ impl SolidColour {
 }
 
 impl Texture for SolidColour {
-    fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
-        return self.colour;
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Colour {
+        self.colour
+    }
+}
+
+// Blends linearly from a to b, across v by default or along a world axis between two
+// coordinates. Values past either end are held at that end's colour.
+pub struct GradientTexture {
+    a: Colour,
+    b: Colour,
+    axis: Option<usize>,
+    start: f64,
+    end: f64,
+}
+
+impl GradientTexture {
+    pub fn new(a: Colour, b: Colour) -> GradientTexture {
+        GradientTexture {
+            a,
+            b,
+            axis: None,
+            start: 0.0,
+            end: 1.0,
+        }
+    }
+
+    pub fn along_axis(a: Colour, b: Colour, axis: usize, start: f64, end: f64) -> GradientTexture {
+        GradientTexture {
+            axis: Some(axis),
+            start,
+            end,
+            ..GradientTexture::new(a, b)
+        }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, _u: f64, v: f64, p: Point3) -> Colour {
+        let position = match self.axis {
+            Some(axis) => p.axis(axis),
+            None => v,
+        };
+        let t = ((position - self.start) / (self.end - self.start)).clamp(0.0, 1.0);
+
+        self.a * (1.0 - t) + self.b * t
     }
 }
 
@@ -39,6 +84,7 @@ pub struct CheckerTexture {
     even: Box<dyn Texture>,
     odd: Box<dyn Texture>,
     scale: f64,
+    uv_mode: bool,
 }
 
 impl CheckerTexture {
@@ -47,12 +93,33 @@ impl CheckerTexture {
             even: Box::new(SolidColour::new(a)),
             odd: Box::new(SolidColour::new(b)),
             scale: 1.0 / scale,
+            uv_mode: false,
+        }
+    }
+
+    // Checks are laid out over the surface UVs rather than world space, scale is the size
+    // of a single check in UV units.
+    pub fn new_uv(scale: f64, a: Colour, b: Colour) -> CheckerTexture {
+        CheckerTexture {
+            uv_mode: true,
+            ..CheckerTexture::new_with_colours(scale, a, b)
         }
     }
 }
 
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
+        if self.uv_mode {
+            let uint = f64::floor(u * self.scale) as i32;
+            let vint = f64::floor(v * self.scale) as i32;
+
+            return if (uint + vint).rem_euclid(2) == 0 {
+                self.even.value(u, v, p)
+            } else {
+                self.odd.value(u, v, p)
+            };
+        }
+
         let xint = f64::floor(p.axis(0) * self.scale) as i32;
         let yint = f64::floor(p.axis(1) * self.scale) as i32;
         let zint = f64::floor(p.axis(2) * self.scale) as i32;
@@ -67,8 +134,108 @@ impl Texture for CheckerTexture {
     }
 }
 
+// Rotates incoming UVs about the origin by rotation degrees, then scales and offsets them
+// before looking up the wrapped texture. A scale of 2 repeats a tiling texture twice as often.
+// The hit point is passed through unchanged.
+pub struct TransformedTexture {
+    texture: Arc<dyn Texture>,
+    scale: (f64, f64),
+    offset: (f64, f64),
+    cos_theta: f64,
+    sin_theta: f64,
+}
+
+impl TransformedTexture {
+    pub fn new(
+        texture: Arc<dyn Texture>,
+        scale: (f64, f64),
+        offset: (f64, f64),
+        rotation: f64,
+    ) -> TransformedTexture {
+        let radians = rotation.to_radians();
+        TransformedTexture {
+            texture,
+            scale,
+            offset,
+            cos_theta: radians.cos(),
+            sin_theta: radians.sin(),
+        }
+    }
+
+    pub fn scaled(texture: Arc<dyn Texture>, u_scale: f64, v_scale: f64) -> TransformedTexture {
+        TransformedTexture::new(texture, (u_scale, v_scale), (0.0, 0.0), 0.0)
+    }
+}
+
+impl Texture for TransformedTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
+        let rotated_u = self.cos_theta * u - self.sin_theta * v;
+        let rotated_v = self.sin_theta * u + self.cos_theta * v;
+
+        self.texture.value(
+            rotated_u * self.scale.0 + self.offset.0,
+            rotated_v * self.scale.1 + self.offset.1,
+            p,
+        )
+    }
+}
+
+// Tiles UV space into square cells spacing wide, with a dot of the given radius in the
+// middle of each. Both radius and spacing are in UV units.
+pub struct DotTexture {
+    background: Arc<dyn Texture>,
+    dot: Arc<dyn Texture>,
+    spacing: f64,
+    radius: f64,
+}
+
+impl DotTexture {
+    pub fn new(
+        background: Arc<dyn Texture>,
+        dot: Arc<dyn Texture>,
+        spacing: f64,
+        radius: f64,
+    ) -> DotTexture {
+        DotTexture {
+            background,
+            dot,
+            spacing,
+            radius,
+        }
+    }
+
+    pub fn new_with_colours(
+        background: Colour,
+        dot: Colour,
+        spacing: f64,
+        radius: f64,
+    ) -> DotTexture {
+        DotTexture::new(
+            SolidColour::as_arc(background),
+            SolidColour::as_arc(dot),
+            spacing,
+            radius,
+        )
+    }
+}
+
+impl Texture for DotTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
+        let cell_u = (u / self.spacing).rem_euclid(1.0) - 0.5;
+        let cell_v = (v / self.spacing).rem_euclid(1.0) - 0.5;
+        let distance_squared = (cell_u * cell_u + cell_v * cell_v) * self.spacing * self.spacing;
+
+        if distance_squared <= self.radius * self.radius {
+            self.dot.value(u, v, p)
+        } else {
+            self.background.value(u, v, p)
+        }
+    }
+}
+
 pub struct ImageTexture {
     image: RgbImage,
+    bilinear: bool,
 }
 
 impl ImageTexture {
@@ -77,12 +244,42 @@ impl ImageTexture {
         P: AsRef<Path>,
     {
         let image = open(path).expect("Image couldn't be opened").into_rgb8();
-        ImageTexture { image }
+        ImageTexture {
+            image,
+            bilinear: true,
+        }
+    }
+
+    pub fn new_nearest<P>(path: P) -> ImageTexture
+    where
+        P: AsRef<Path>,
+    {
+        ImageTexture {
+            bilinear: false,
+            ..ImageTexture::new(path)
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Colour {
+        let (image_width, image_height) = self.image.dimensions();
+
+        let x = x.clamp(0, image_width as i64 - 1) as u32;
+        let y = y.clamp(0, image_height as i64 - 1) as u32;
+
+        let pixel = self.image.get_pixel(x, y);
+
+        let colour_scale = 1.0 / 255.0;
+
+        let r = pixel.0[0] as f64 * colour_scale;
+        let g = pixel.0[1] as f64 * colour_scale;
+        let b = pixel.0[2] as f64 * colour_scale;
+
+        Colour::new(r, g, b)
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Colour {
         let (image_width, image_height) = self.image.dimensions();
 
         let uclamp = f64::clamp(u, 0.0, 1.0);
@@ -91,36 +288,380 @@ impl Texture for ImageTexture {
         let x = uclamp * image_width as f64;
         let y = vclamp * image_height as f64;
 
-        let pixel = self.image.get_pixel(x as u32, y as u32);
+        if !self.bilinear {
+            return self.texel(x as i64, y as i64);
+        }
 
-        let colour_scale = 1.0 / 255.0;
+        // Texel centres sit at half-integer coordinates, so shift before finding the 2x2 footprint.
+        let x = x - 0.5;
+        let y = y - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
 
-        let r = pixel.0[0] as f64 * colour_scale;
-        let g = pixel.0[1] as f64 * colour_scale;
-        let b = pixel.0[2] as f64 * colour_scale;
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
 
-        Colour::new(r, g, b)
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Keeps the full floating point range of HDR and EXR images, where ImageTexture would clamp
+// everything to 8 bits.
+pub struct HdrTexture {
+    image: Rgb32FImage,
+}
+
+impl HdrTexture {
+    pub fn open<P>(path: P) -> io::Result<HdrTexture>
+    where
+        P: AsRef<Path>,
+    {
+        let image = open(path).map_err(io::Error::other)?.into_rgb32f();
+        Ok(HdrTexture { image })
+    }
+}
+
+impl Texture for HdrTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Colour {
+        let (image_width, image_height) = self.image.dimensions();
+
+        let x = (f64::clamp(u, 0.0, 1.0) * image_width as f64) as u32;
+        let y = ((1.0 - f64::clamp(v, 0.0, 1.0)) * image_height as f64) as u32;
+
+        let pixel = self
+            .image
+            .get_pixel(x.min(image_width - 1), y.min(image_height - 1));
+
+        Colour::new(pixel.0[0] as f64, pixel.0[1] as f64, pixel.0[2] as f64)
     }
 }
 
 pub struct NoiseTexture {
     noise: Turbulence<Perlin, Perlin>,
+    scale: f64,
+    marble: bool,
 }
 
 impl NoiseTexture {
     pub fn new() -> NoiseTexture {
-        let mut noise = Turbulence::new(Perlin::new(1));
-        noise = noise.set_frequency(150.0);
-        NoiseTexture { noise }
+        NoiseTexture::new_with_seed(1)
+    }
+
+    pub fn new_with_seed(seed: u32) -> NoiseTexture {
+        NoiseTexture::new_with(seed, 150.0)
+    }
+
+    pub fn new_with(seed: u32, frequency: f64) -> NoiseTexture {
+        let mut noise = Turbulence::new(Perlin::new(seed));
+        noise = noise.set_seed(seed).set_frequency(frequency);
+        NoiseTexture {
+            noise,
+            scale: 1.0,
+            marble: false,
+        }
+    }
+
+    pub fn new_with_scale(scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            scale,
+            ..NoiseTexture::new()
+        }
+    }
+
+    // Veins run across z, with the noise pushing the phase of each stripe around.
+    pub fn marble(seed: u32, scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            scale,
+            marble: true,
+            ..NoiseTexture::new_with_seed(seed)
+        }
+    }
+}
+
+impl Default for NoiseTexture {
+    fn default() -> Self {
+        NoiseTexture::new()
     }
 }
 
 impl Texture for NoiseTexture {
-    fn value(&self, u: f64, v: f64, p: Point3) -> Colour {
-        let point = [u, v];
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Colour {
+        let point = [
+            p.axis(0) * self.scale,
+            p.axis(1) * self.scale,
+            p.axis(2) * self.scale,
+        ];
 
         let noise = self.noise.get(point);
 
+        if self.marble {
+            let phase = point[2] + 10.0 * noise;
+            return Colour::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + f64::sin(phase));
+        }
+
         Colour::new(1.0, 1.0, 1.0) * 0.5 * (1.0 + noise)
     }
 }
+
+// Concentric growth rings around a grain axis, one ring every ring_spacing units out from it.
+// Distortion is how far, in radians of ring phase, Perlin noise pushes the rings out of round.
+// It starts at zero, leaving every point at the same distance from the axis in the same band.
+pub struct WoodTexture {
+    light: Colour,
+    dark: Colour,
+    ring_spacing: f64,
+    distortion: f64,
+    origin: Point3,
+    axis: Vec3,
+    noise: Perlin,
+}
+
+impl WoodTexture {
+    // Grain runs along y through the origin.
+    pub fn new(light: Colour, dark: Colour, ring_spacing: f64) -> WoodTexture {
+        WoodTexture {
+            light,
+            dark,
+            ring_spacing,
+            distortion: 0.0,
+            origin: Point3::new(0.0, 0.0, 0.0),
+            axis: Vec3::new(0.0, 1.0, 0.0),
+            noise: Perlin::new(1),
+        }
+    }
+
+    pub fn with_distortion(self, distortion: f64) -> WoodTexture {
+        WoodTexture { distortion, ..self }
+    }
+
+    pub fn with_grain(self, origin: Point3, axis: Vec3) -> WoodTexture {
+        WoodTexture {
+            origin,
+            axis: unit_vector(axis),
+            ..self
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Colour {
+        let offset = Vec3::from(p - self.origin);
+        let radius = (offset - dot(offset, self.axis) * self.axis).length();
+
+        let noise = self.noise.get([p.axis(0), p.axis(1), p.axis(2)]);
+        let phase = 2.0 * f64::consts::PI * radius / self.ring_spacing + self.distortion * noise;
+        let t = 0.5 * (1.0 + f64::sin(phase));
+
+        self.light * (1.0 - t) + self.dark * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::quad::Quad;
+    use crate::testing::*;
+
+    fn origin() -> Point3 {
+        Point3::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn bilinear_lookup_at_the_centre_averages_a_2x2_checkerboard() {
+        let path = temp_path("checker.png");
+        let mut checker = RgbImage::new(2, 2);
+        for (x, y, pixel) in checker.enumerate_pixels_mut() {
+            *pixel = image::Rgb(if (x + y) % 2 == 0 {
+                [255, 0, 0]
+            } else {
+                [0, 0, 255]
+            });
+        }
+        checker.save(&path).unwrap();
+        let smooth = ImageTexture::new(&path);
+        let nearest = ImageTexture::new_nearest(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let centre = smooth.value(0.5, 0.5, origin());
+        assert!(centre.approx_eq(&Colour::new(0.5, 0.0, 0.5), 1e-12));
+        // Nearest keeps the hard texel, here the bottom right one.
+        assert_eq!(
+            nearest.value(0.5, 0.5, origin()),
+            Colour::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(smooth.value(0.0, 1.0, origin()), Colour::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn noise_differs_between_distant_points() {
+        let noise = NoiseTexture::new();
+        let near = noise.value(0.0, 0.0, Point3::new(0.1, 0.2, 0.3));
+        let far = noise.value(0.0, 0.0, Point3::new(40.1, -17.3, 8.9));
+        assert_ne!(near, far);
+    }
+
+    #[test]
+    fn marble_with_different_seeds_gives_different_textures() {
+        let first = NoiseTexture::marble(1, 4.0);
+        let second = NoiseTexture::marble(2, 4.0);
+        let points = [
+            Point3::new(0.1, 0.2, 0.3),
+            Point3::new(1.7, -0.4, 2.2),
+            Point3::new(-3.3, 0.9, 0.05),
+        ];
+        assert!(
+            points
+                .iter()
+                .any(|&p| first.value(0.0, 0.0, p) != second.value(0.0, 0.0, p))
+        );
+    }
+
+    #[test]
+    fn scale_stretches_the_turbulence_lookup() {
+        let p = Point3::new(0.3, -0.7, 1.1);
+        let doubled = Point3::new(0.6, -1.4, 2.2);
+        assert_eq!(
+            NoiseTexture::new_with_scale(2.0).value(0.0, 0.0, p),
+            NoiseTexture::new().value(0.0, 0.0, doubled)
+        );
+        assert_eq!(
+            NoiseTexture::marble(1, 2.0).value(0.0, 0.0, p),
+            NoiseTexture::marble(1, 1.0).value(0.0, 0.0, doubled)
+        );
+    }
+
+    #[test]
+    fn different_seeds_give_different_values_at_the_same_point() {
+        let p = Point3::new(0.3, -0.7, 1.1);
+        assert_ne!(
+            NoiseTexture::new_with(1, 150.0).value(0.0, 0.0, p),
+            NoiseTexture::new_with(2, 150.0).value(0.0, 0.0, p)
+        );
+    }
+
+    #[test]
+    fn a_uv_checker_on_a_quad_alternates_along_u_wherever_the_quad_sits() {
+        let (white, black) = (Colour::new(1.0, 1.0, 1.0), Colour::new(0.0, 0.0, 0.0));
+        let checker = CheckerTexture::new_uv(0.125, white, black);
+        let row_along_u = |corner: Point3| -> Vec<Colour> {
+            let quad = Quad::new(
+                corner,
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                grey(),
+            );
+            (0..8)
+                .map(|k| {
+                    let x = corner.axis(0) + (k as f64 + 0.5) / 4.0;
+                    let origin = Point3::new(x, corner.axis(1) + 0.1, corner.axis(2) + 1.0);
+                    let hit = quad
+                        .hit(
+                            &ray(origin, Vec3::new(0.0, 0.0, -1.0)),
+                            0.001,
+                            f64::INFINITY,
+                        )
+                        .unwrap();
+                    checker.value(hit.u, hit.v, hit.hit_pos())
+                })
+                .collect()
+        };
+
+        let here = row_along_u(Point3::new(0.0, 0.0, 0.0));
+        assert!(here.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(here, row_along_u(Point3::new(37.3, -11.6, 5.2)));
+    }
+
+    #[test]
+    fn a_gradient_runs_from_a_at_v_0_to_b_at_v_1() {
+        let (a, b) = (Colour::new(0.2, 0.4, 1.0), Colour::new(1.0, 0.6, 0.0));
+        let ramp = GradientTexture::new(a, b);
+
+        assert_eq!(ramp.value(0.3, 0.0, origin()), a);
+        assert_eq!(ramp.value(0.3, 1.0, origin()), b);
+        assert!(
+            ramp.value(0.3, 0.5, origin())
+                .approx_eq(&Colour::new(0.6, 0.5, 0.5), 1e-12)
+        );
+    }
+
+    #[test]
+    fn wood_at_equal_radius_is_the_same_band_at_every_angle() {
+        let (light, dark) = (Colour::new(0.8, 0.6, 0.4), Colour::new(0.4, 0.25, 0.1));
+        let wood = WoodTexture::new(light, dark, 0.3)
+            .with_grain(Point3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0));
+
+        for radius in [0.1, 0.45, 1.32] {
+            let first = wood.value(0.0, 0.0, Point3::new(1.0 + radius, 0.0, 1.0));
+            for step in 1..12 {
+                let angle = step as f64 * 0.5;
+                let p = Point3::new(
+                    1.0 + radius * angle.cos(),
+                    0.7 * step as f64,
+                    1.0 + radius * angle.sin(),
+                );
+                assert!(wood.value(0.0, 0.0, p).approx_eq(&first, 1e-9));
+            }
+        }
+
+        // A quarter ring further out lands in a different part of the band.
+        let inner = wood.value(0.0, 0.0, Point3::new(1.1, 0.0, 1.0));
+        let outer = wood.value(0.0, 0.0, Point3::new(1.175, 0.0, 1.0));
+        assert!(!inner.approx_eq(&outer, 1e-3));
+
+        let knotted = WoodTexture::new(light, dark, 0.3).with_distortion(2.0);
+        assert_ne!(
+            knotted.value(0.0, 0.0, Point3::new(0.45, 0.3, 0.0)),
+            knotted.value(0.0, 0.0, Point3::new(0.0, 0.3, 0.45))
+        );
+    }
+
+    #[test]
+    fn a_uv_scale_of_2_repeats_a_checker_twice_as_often_across_a_quad() {
+        let quad = Quad::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            grey(),
+        );
+        let checker: Arc<dyn Texture> = Arc::new(CheckerTexture::new_uv(
+            0.25,
+            Colour::new(1.0, 1.0, 1.0),
+            Colour::new(0.0, 0.0, 0.0),
+        ));
+        let tiled = TransformedTexture::scaled(checker.clone(), 2.0, 2.0);
+
+        // Colour changes seen walking across the quad along u.
+        let changes = |texture: &dyn Texture| {
+            let row: Vec<Colour> = (0..64)
+                .map(|k| {
+                    let x = -1.0 + (k as f64 + 0.5) / 32.0;
+                    let towards = ray(Point3::new(x, 0.1, 0.0), Vec3::new(0.0, 0.0, -1.0));
+                    let hit = quad.hit(&towards, 0.001, f64::INFINITY).unwrap();
+                    texture.value(hit.u, hit.v, hit.hit_pos())
+                })
+                .collect();
+            row.windows(2).filter(|pair| pair[0] != pair[1]).count()
+        };
+
+        assert_eq!(changes(checker.as_ref()), 3);
+        assert_eq!(changes(&tiled), 7);
+    }
+
+    #[test]
+    fn a_dot_sits_at_the_cell_centre_and_background_at_its_corner() {
+        let (background, dot) = (Colour::new(0.9, 0.9, 0.8), Colour::new(0.8, 0.1, 0.1));
+        let dots = DotTexture::new_with_colours(background, dot, 0.25, 0.08);
+
+        for (cell_u, cell_v) in [(0.0, 0.0), (0.5, 0.25), (-0.75, 1.0)] {
+            assert_eq!(dots.value(cell_u + 0.125, cell_v + 0.125, origin()), dot);
+            assert_eq!(
+                dots.value(cell_u + 0.001, cell_v + 0.001, origin()),
+                background
+            );
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::bounding::*;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::*;
+use crate::vec3::*;
+
+use std::sync::Arc;
+
+pub struct Triangle {
+    a: Point3,
+    mat: Arc<dyn Material>,
+    bounds: BoundingBox,
+
+    edge1: Vec3,
+    edge2: Vec3,
+    normal: Vec3,
+
+    uvs: [(f64, f64); 3],
+    // Directions of increasing u and v across the face.
+    tangent_frame: Option<(Vec3, Vec3)>,
+    // Per-vertex shading normals, interpolated across the face when set.
+    vertex_normals: Option<[Vec3; 3]>,
+}
+
+impl Triangle {
+    // With these texture coordinates the interpolated UV is just the barycentric (u, v).
+    pub fn new(a: Point3, b: Point3, c: Point3, mat: Arc<dyn Material>) -> Triangle {
+        Triangle::with_uvs(a, b, c, [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], mat)
+    }
+
+    pub fn with_uvs(
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        uvs: [(f64, f64); 3],
+        mat: Arc<dyn Material>,
+    ) -> Triangle {
+        let bound1 = BoundingBox::new(a, b);
+        let bound2 = BoundingBox::new(a, c);
+
+        let full_bounds = BoundingBox::box_between(&bound1, &bound2);
+
+        let edge1: Vec3 = (b - a).into();
+        let edge2: Vec3 = (c - a).into();
+        let normal = unit_vector(cross(edge1, edge2));
+
+        // Solve edge = dP/du * du + dP/dv * dv over both edges for dP/du and dP/dv.
+        // Degenerate UVs leave the triangle without a tangent frame.
+        let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+        let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+        let uv_det = du1 * dv2 - du2 * dv1;
+        let tangent_frame = (f64::abs(uv_det) > 1e-12).then(|| {
+            (
+                unit_vector((edge1 * dv2 - edge2 * dv1) / uv_det),
+                unit_vector((edge2 * du1 - edge1 * du2) / uv_det),
+            )
+        });
+
+        Triangle {
+            a,
+            mat,
+            bounds: full_bounds,
+            edge1,
+            edge2,
+            normal,
+            uvs,
+            tangent_frame,
+            vertex_normals: None,
+        }
+    }
+
+    // Shades the triangle as if it were part of a smooth surface with these normals at a, b
+    // and c. The flat face normal still decides which side a ray hit from.
+    pub fn set_vertex_normals(&mut self, normals: [Vec3; 3]) {
+        self.vertex_normals = Some(normals.map(unit_vector));
+    }
+
+    // Normal used for shading at barycentric (u, v), pointing the same way as the face normal.
+    pub fn shading_normal(&self, u: f64, v: f64) -> Vec3 {
+        match self.vertex_normals {
+            Some([na, nb, nc]) => unit_vector((1.0 - u - v) * na + u * nb + v * nc),
+            None => self.normal,
+        }
+    }
+
+    pub fn boxed(a: Point3, b: Point3, c: Point3, mat: Arc<dyn Material>) -> Box<Triangle> {
+        Box::new(Triangle::new(a, b, c, mat))
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let pvec = cross(r.direction(), self.edge2);
+        let det = dot(self.edge1, pvec);
+
+        if f64::abs(det) < 1e-8 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let tvec: Vec3 = (r.origin() - self.a).into();
+
+        let u = dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = cross(tvec, self.edge1);
+        let v = dot(r.direction(), qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = dot(self.edge2, qvec) * inv_det;
+        if ray_tmin > t || t > ray_tmax {
+            return None;
+        }
+
+        let w = 1.0 - u - v;
+        let tex_u = w * self.uvs[0].0 + u * self.uvs[1].0 + v * self.uvs[2].0;
+        let tex_v = w * self.uvs[0].1 + u * self.uvs[1].1 + v * self.uvs[2].1;
+
+        let mut record =
+            HitRecord::with_uv(r.at(t), self.normal, t, self.mat.clone(), tex_u, tex_v);
+        record.set_face_normal(r, self.normal);
+        if self.vertex_normals.is_some() {
+            let shading_normal = self.shading_normal(u, v);
+            let facing = if record.front_face() {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+            record.update_record(record.hit_pos(), facing, t);
+        }
+        if let Some((tangent, bitangent)) = self.tangent_frame {
+            record.set_tangent_frame(tangent, bitangent);
+        }
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> &BoundingBox {
+        &self.bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    // In the z = -2 plane, facing +z towards the origin.
+    fn facing_origin() -> Triangle {
+        Triangle::new(
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(-1.0, 1.0, -2.0),
+            grey(),
+        )
+    }
+
+    #[test]
+    fn an_interior_hit_reports_its_barycentric_coordinates() {
+        let towards = ray(Point3::new(-0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = facing_origin().hit(&towards, 0.001, f64::INFINITY).unwrap();
+
+        assert!((hit.t - 2.0).abs() < 1e-12);
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+        assert!(hit.front_face());
+        assert!(hit.normal().approx_eq(&Vec3::new(0.0, 0.0, 1.0), 1e-12));
+    }
+
+    #[test]
+    fn a_ray_just_past_the_long_edge_misses() {
+        let past_edge = ray(Point3::new(0.05, 0.05, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(
+            facing_origin()
+                .hit(&past_edge, 0.001, f64::INFINITY)
+                .is_none()
+        );
+        let inside = ray(Point3::new(-0.05, -0.05, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(facing_origin().hit(&inside, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn a_back_face_hit_flips_the_normal_towards_the_ray() {
+        let from_behind = ray(Point3::new(-0.5, -0.5, -4.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = facing_origin()
+            .hit(&from_behind, 0.001, f64::INFINITY)
+            .unwrap();
+
+        assert!(!hit.front_face());
+        assert!(hit.normal().approx_eq(&Vec3::new(0.0, 0.0, -1.0), 1e-12));
+    }
+
+    #[test]
+    fn vertex_uvs_are_interpolated_across_the_face() {
+        let triangle = Triangle::with_uvs(
+            Point3::new(-1.0, -1.0, -2.0),
+            Point3::new(1.0, -1.0, -2.0),
+            Point3::new(-1.0, 1.0, -2.0),
+            [(0.5, 0.5), (1.0, 0.5), (0.5, 1.0)],
+            grey(),
+        );
+        let towards = ray(
+            Point3::new(0.0, -1.0 + 1e-9, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let hit = triangle.hit(&towards, 0.001, f64::INFINITY).unwrap();
+
+        // Half way along the edge from a to b.
+        assert!((hit.u - 0.75).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn interpolated_normals_match_the_vertices_and_average_at_the_centroid() {
+        let normals = [
+            unit_vector(Vec3::new(-0.5, -0.5, 1.0)),
+            unit_vector(Vec3::new(0.5, -0.5, 1.0)),
+            unit_vector(Vec3::new(-0.5, 0.5, 1.0)),
+        ];
+        let mut smooth = facing_origin();
+        smooth.set_vertex_normals(normals);
+
+        assert!(
+            smooth
+                .shading_normal(0.0, 0.0)
+                .approx_eq(&normals[0], 1e-12)
+        );
+        assert!(
+            smooth
+                .shading_normal(1.0, 0.0)
+                .approx_eq(&normals[1], 1e-12)
+        );
+        assert!(
+            smooth
+                .shading_normal(0.0, 1.0)
+                .approx_eq(&normals[2], 1e-12)
+        );
+
+        let average = unit_vector(normals[0] + normals[1] + normals[2]);
+        let third = 1.0 / 3.0;
+        assert!(
+            smooth
+                .shading_normal(third, third)
+                .approx_eq(&average, 1e-12)
+        );
+
+        // A hit at the centroid shades with the average but still decides its side by the face.
+        let centroid = Point3::new(-1.0 / 3.0, -1.0 / 3.0, 0.0);
+        let hit = smooth
+            .hit(
+                &ray(centroid, Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!(hit.normal().approx_eq(&average, 1e-12));
+        assert!(hit.front_face());
+    }
+}
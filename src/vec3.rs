@@ -1,9 +1,16 @@
 use std::fmt::{Display, Formatter, Result};
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
 
 use rand::Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+// Display gamma assumed when none is configured, close to what sRGB monitors expect.
+pub const DEFAULT_GAMMA: f64 = 2.2;
+
+// All three vector types serialise as a plain [x, y, z] array.
 #[derive(Copy, Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
 pub struct Vec3 {
     e: [f64; 3],
 }
@@ -42,6 +49,10 @@ impl Vec3 {
         (f64::abs(self.e[0]) < s) && (f64::abs(self.e[1]) < s) && (f64::abs(self.e[2]) < s)
     }
 
+    pub fn approx_eq(&self, other: &Vec3, epsilon: f64) -> bool {
+        (0..3).all(|i| f64::abs(self.e[i] - other.e[i]) < epsilon)
+    }
+
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
         *self - 2.0 * dot(*self, *normal) * *normal
     }
@@ -53,15 +64,13 @@ impl Vec3 {
         r_out_parallel + r_out_perp
     }
 
-    pub fn random() -> Vec3 {
-        let mut rng = rand::rng();
+    pub fn random(rng: &mut (impl Rng + ?Sized)) -> Vec3 {
         Vec3 {
             e: [rng.random(), rng.random(), rng.random()],
         }
     }
 
-    pub fn random_with_range(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::rng();
+    pub fn random_with_range(rng: &mut (impl Rng + ?Sized), min: f64, max: f64) -> Vec3 {
         Vec3 {
             e: [
                 rng.random_range(min..max),
@@ -71,9 +80,9 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit_vector() -> Vec3 {
+    pub fn random_unit_vector(rng: &mut (impl Rng + ?Sized)) -> Vec3 {
         loop {
-            let p = Vec3::random_with_range(-1.0, 1.0);
+            let p = Vec3::random_with_range(rng, -1.0, 1.0);
             let lensq = p.length_squared();
             if lensq <= 1.0 && lensq > 1e-160 {
                 return p / f64::sqrt(lensq);
@@ -81,8 +90,8 @@ impl Vec3 {
         }
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vector();
+    pub fn random_on_hemisphere(rng: &mut (impl Rng + ?Sized), normal: &Vec3) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit_vector(rng);
         if dot(on_unit_sphere, *normal) > 0.0 {
             on_unit_sphere
         } else {
@@ -90,8 +99,22 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::rng();
+    // Direction in the hemisphere around a unit normal with density cos(theta) / pi, made by
+    // lifting a uniform point on the unit disc onto the hemisphere. It never lands in the
+    // tangent plane, so the result is always usable as a ray direction.
+    pub fn random_cosine_direction(rng: &mut (impl Rng + ?Sized), normal: &Vec3) -> Vec3 {
+        let r1: f64 = rng.random();
+        let r2: f64 = rng.random();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let radius = f64::sqrt(r2);
+
+        let (u, v) = orthonormal_basis(*normal);
+        (radius * f64::cos(phi) * u)
+            + (radius * f64::sin(phi) * v)
+            + (f64::sqrt(1.0 - r2) * *normal)
+    }
+
+    pub fn random_in_unit_disk(rng: &mut (impl Rng + ?Sized)) -> Vec3 {
         loop {
             let p = Vec3::new(
                 rng.random_range(-1.0..1.0),
@@ -237,10 +260,36 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
     )
 }
 
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &f64 {
+        &self.e[index]
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, index: usize) -> &mut f64 {
+        &mut self.e[index]
+    }
+}
+
 pub fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
+// Two unit vectors perpendicular to the unit vector w and to each other, (u, v, w) is right handed.
+pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+    let a = if f64::abs(w.x()) > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = unit_vector(cross(w, a));
+    let u = cross(w, v);
+    (u, v)
+}
+
 impl From<Point3> for Vec3 {
     fn from(point: Point3) -> Self {
         point.data
@@ -248,6 +297,7 @@ impl From<Point3> for Vec3 {
 }
 
 #[derive(Copy, Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
 pub struct Point3 {
     data: Vec3,
 }
@@ -277,7 +327,7 @@ impl Point3 {
 
     pub fn modify_axis<F>(&mut self, axis: usize, fun: F)
     where
-        F: Fn(f64) -> f64,
+        F: FnOnce(f64) -> f64,
     {
         let current_val = self.data.axis(axis);
         let updated = fun(current_val);
@@ -285,47 +335,19 @@ impl Point3 {
     }
 
     pub fn most_minimum(&self, other: Point3) -> Point3 {
-        let x = if self.axis(0) <= other.axis(0) {
-            self.axis(0)
-        } else {
-            other.axis(0)
-        };
-        let y = if self.axis(1) <= other.axis(1) {
-            self.axis(1)
-        } else {
-            other.axis(1)
-        };
-        let z = if self.axis(2) <= other.axis(2) {
-            self.axis(2)
-        } else {
-            other.axis(2)
-        };
-
-        Point3 {
-            data: Vec3::new(x, y, z),
-        }
+        Point3::new(
+            f64::min(self.axis(0), other.axis(0)),
+            f64::min(self.axis(1), other.axis(1)),
+            f64::min(self.axis(2), other.axis(2)),
+        )
     }
 
     pub fn most_maximum(&self, other: Point3) -> Point3 {
-        let x = if self.axis(0) >= other.axis(0) {
-            self.axis(0)
-        } else {
-            other.axis(0)
-        };
-        let y = if self.axis(1) >= other.axis(1) {
-            self.axis(1)
-        } else {
-            other.axis(1)
-        };
-        let z = if self.axis(2) >= other.axis(2) {
-            self.axis(2)
-        } else {
-            other.axis(2)
-        };
-
-        Point3 {
-            data: Vec3::new(x, y, z),
-        }
+        Point3::new(
+            f64::max(self.axis(0), other.axis(0)),
+            f64::max(self.axis(1), other.axis(1)),
+            f64::max(self.axis(2), other.axis(2)),
+        )
     }
 }
 
@@ -375,7 +397,8 @@ impl Add<Vec3> for Point3 {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
 pub struct Colour {
     data: Vec3,
 }
@@ -399,17 +422,53 @@ impl Colour {
         self.data.e[2]
     }
 
-    pub fn gamma_corrected(&self) -> Colour {
-        let r = Colour::correct_component(self.r());
-        let g = Colour::correct_component(self.g());
-        let b = Colour::correct_component(self.b());
+    pub fn map<F>(&self, fun: F) -> Colour
+    where
+        F: Fn(f64) -> f64,
+    {
+        Colour::new(fun(self.r()), fun(self.g()), fun(self.b()))
+    }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.r().is_finite() && self.g().is_finite() && self.b().is_finite()
+    }
+
+    pub fn max_component(&self) -> f64 {
+        f64::max(self.r(), f64::max(self.g(), self.b()))
+    }
+
+    pub fn approx_eq(&self, other: &Colour, epsilon: f64) -> bool {
+        self.data.approx_eq(&other.data, epsilon)
+    }
+
+    // Encodes linear values as c^(1/gamma) for display. A gamma of 1.0 leaves them linear.
+    pub fn gamma_corrected(&self, gamma: f64) -> Colour {
+        let r = Colour::correct_component(self.r(), gamma);
+        let g = Colour::correct_component(self.g(), gamma);
+        let b = Colour::correct_component(self.b(), gamma);
 
         Colour::new(r, g, b)
     }
 
-    fn correct_component(component: f64) -> f64 {
+    // The 8-bit "r g b" a PPM pixel is written as. This replaces the Display impl, which
+    // had no way to be told the camera's gamma, so format colours through here instead.
+    pub fn to_bytes(&self, gamma: f64) -> [u8; 3] {
+        let corrected = self.gamma_corrected(gamma);
+
+        [
+            (256.0 * corrected.r().clamp(0.0, 0.999)) as u8,
+            (256.0 * corrected.g().clamp(0.0, 0.999)) as u8,
+            (256.0 * corrected.b().clamp(0.0, 0.999)) as u8,
+        ]
+    }
+
+    fn correct_component(component: f64, gamma: f64) -> f64 {
         if component > 0.0 {
-            f64::sqrt(component)
+            f64::powf(component, 1.0 / gamma)
         } else {
             0.0
         }
@@ -468,14 +527,118 @@ impl From<Vec3> for Colour {
     }
 }
 
-impl Display for Colour {
-    fn fmt(&self, f: &mut Formatter) -> Result {
-        let corrected = self.gamma_corrected();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reflect_mirrors_a_45_degree_ray() {
+        let incoming = unit_vector(Vec3::new(1.0, -1.0, 0.0));
+        let reflected = incoming.reflect(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(reflected.approx_eq(&unit_vector(Vec3::new(1.0, 1.0, 0.0)), 1e-12));
+    }
+
+    #[test]
+    fn refract_with_matched_indices_is_a_no_op() {
+        let incoming = unit_vector(Vec3::new(0.3, -1.0, 0.2));
+        let refracted = incoming.refract(&Vec3::new(0.0, 1.0, 0.0), 1.0);
+        assert!(refracted.approx_eq(&incoming, 1e-12));
+    }
+
+    #[test]
+    fn near_zero_only_for_tiny_components() {
+        assert!(Vec3::new(1e-9, 1e-9, 1e-9).near_zero());
+        assert!(!Vec3::new(0.0, 0.0, 1e-3).near_zero());
+    }
+
+    #[test]
+    fn colours_multiply_componentwise() {
+        let product = Colour::new(0.5, 0.5, 0.5) * Colour::new(0.2, 0.4, 0.8);
+        assert!(product.approx_eq(&Colour::new(0.1, 0.2, 0.4), 1e-12));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn colour_serialises_as_a_plain_array() {
+        let json = serde_json::to_string(&Colour::new(0.1, 0.2, 0.3)).unwrap();
+        assert_eq!(json, "[0.1,0.2,0.3]");
+        let back: Colour = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, Colour::new(0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn to_bytes_encodes_with_the_given_gamma() {
+        let grey = Colour::new(0.25, 0.25, 0.25);
+        assert_eq!(grey.to_bytes(1.0), [64, 64, 64]);
+        assert_eq!(grey.to_bytes(2.0), [128, 128, 128]);
+    }
+
+    #[test]
+    fn axis_reads_and_modify_axis_writes_one_component() {
+        let mut p = Point3::new(1.0, 2.0, 3.0);
+        assert_eq!(p.axis(1), 2.0);
+        assert_eq!(Vec3::new(1.0, 2.0, 3.0).axis(2), 3.0);
+
+        p.modify_axis(0, |x| x + 4.0);
+        assert_eq!(p, Point3::new(5.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn most_minimum_and_most_maximum_pick_per_axis() {
+        let a = Point3::new(1.0, 5.0, -2.0);
+        let b = Point3::new(3.0, -1.0, -2.5);
+        assert_eq!(a.most_minimum(b), Point3::new(1.0, -1.0, -2.5));
+        assert_eq!(a.most_maximum(b), Point3::new(3.0, 5.0, -2.0));
+    }
+
+    #[test]
+    fn index_reads_and_writes_components() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!([v[0], v[1], v[2]], [1.0, 2.0, 3.0]);
+
+        v[1] = 7.0;
+        v[2] *= 2.0;
+        assert_eq!(v, Vec3::new(1.0, 7.0, 6.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_past_the_third_component_panics() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let _ = v[3];
+    }
+
+    #[test]
+    fn approx_eq_depends_on_the_epsilon() {
+        let (a, b) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 3.0000001));
+        assert!(a.approx_eq(&b, 1e-5));
+        assert!(!a.approx_eq(&b, 1e-9));
+
+        let (c, d) = (Colour::new(1.0, 2.0, 3.0), Colour::new(1.0, 2.0, 3.0000001));
+        assert!(c.approx_eq(&d, 1e-5));
+        assert!(!c.approx_eq(&d, 1e-9));
+    }
+
+    #[test]
+    fn cosine_directions_have_a_mean_cosine_of_two_thirds() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
 
-        let rbyte: i32 = (256.0 * corrected.r().clamp(0.0, 0.999)) as i32;
-        let gbyte: i32 = (256.0 * corrected.g().clamp(0.0, 0.999)) as i32;
-        let bbyte: i32 = (256.0 * corrected.b().clamp(0.0, 0.999)) as i32;
+        let normal = unit_vector(Vec3::new(1.0, 2.0, -2.0));
+        let mut rng = SmallRng::seed_from_u64(106);
+        let cosines: Vec<f64> = (0..20000)
+            .map(|_| {
+                let direction = Vec3::random_cosine_direction(&mut rng, &normal);
+                assert!((direction.length() - 1.0).abs() < 1e-9);
+                dot(direction, normal)
+            })
+            .collect();
+        assert!(cosines.iter().all(|&c| c >= 0.0));
 
-        write!(f, "{} {} {}\n", rbyte, gbyte, bbyte)
+        // Cosine weighting makes cos^2 uniform, so the mean is 2/3 and 3/4 lie above 0.5.
+        let mean = cosines.iter().sum::<f64>() / cosines.len() as f64;
+        assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean cosine {mean}");
+        let steep = cosines.iter().filter(|&&c| c > 0.5).count() as f64 / cosines.len() as f64;
+        assert!((steep - 0.75).abs() < 0.015, "{steep} above 0.5");
     }
 }
@@ -0,0 +1,20 @@
+use crate::bvh::BvhTree;
+use crate::hittable::*;
+
+// Everything a camera renders. Lights duplicate the emissive shapes in world that diffuse
+// bounces should sample directly, weighted against the material's own sampling with the
+// power heuristic. They are never rendered themselves, and can be left empty.
+pub struct Scene {
+    pub world: BvhTree,
+    pub lights: HittableList,
+}
+
+impl Scene {
+    pub fn new(world: BvhTree) -> Scene {
+        Scene::with_lights(world, HittableList::new())
+    }
+
+    pub fn with_lights(world: BvhTree, lights: HittableList) -> Scene {
+        Scene { world, lights }
+    }
+}